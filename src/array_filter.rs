@@ -0,0 +1,671 @@
+use std::cmp::Ordering;
+
+use kstring::KStringCow;
+use liquid::ValueView;
+use liquid_core::model::object::{ObjectRender, ObjectSource};
+use liquid_core::model::value::{DisplayCow, ValueViewCmp};
+use liquid_core::model::{ObjectView, ScalarCow, State, ValueCow};
+use liquid_core::parser::FilterArguments;
+use liquid_core::runtime::Stack;
+use liquid_core::{
+    Display_filter, Expression, Filter, FilterParameters, FilterReflection, FromFilterParameters,
+    ParseFilter, Result, Runtime, Value,
+};
+
+use crate::util::invalid_argument;
+
+/// arrays are used as they are, nil is empty and anything else is an array of one
+fn as_items(input: &dyn ValueView) -> Vec<&dyn ValueView> {
+    if let Some(array) = input.as_array() {
+        array.values().collect()
+    } else if input.is_nil() {
+        Vec::new()
+    } else {
+        vec![input]
+    }
+}
+
+/// `author.name` looks up `name` in `author`
+fn property<'a>(item: &'a dyn ValueView, path: &str) -> Option<&'a dyn ValueView> {
+    path.split('.')
+        .try_fold(item, |value, key| value.as_object()?.get(key))
+        .filter(|value| !value.is_nil())
+}
+
+/// like jekyll values are compared as strings so `2020` matches `"2020"`, if the property is an
+/// array e.g. `tags` then any of it's values can match. Without a target the property just has to
+/// be truthy
+fn matches(value: Option<&dyn ValueView>, target: Option<&dyn ValueView>) -> bool {
+    let same = |a: &dyn ValueView, b: &dyn ValueView| a.to_kstr() == b.to_kstr();
+    match (value, target) {
+        (value, None) => value.is_some_and(|v| v.query_state(State::Truthy)),
+        (None, Some(target)) => target.is_nil(),
+        (Some(_), Some(target)) if target.is_nil() => false,
+        (Some(value), Some(target)) => match value.as_array() {
+            Some(array) => array.values().any(|v| same(v, target)),
+            None => same(value, target),
+        },
+    }
+}
+
+/// the item bound to it's name on top of the caller's variables, so an expression can use
+/// loop variables, `assign`s and `include.*` as well as `page`, `global` etc. Nothing is
+/// copied, anything that isn't the item is looked up in the caller's stack
+#[derive(Debug)]
+struct ItemScope<'a> {
+    name: &'a str,
+    item: &'a dyn ValueView,
+    stack: &'a Stack<'a>,
+}
+
+impl ValueView for ItemScope<'_> {
+    fn as_debug(&self) -> &dyn std::fmt::Debug {
+        self
+    }
+
+    fn render(&self) -> DisplayCow<'_> {
+        DisplayCow::Owned(Box::new(ObjectRender::new(self)))
+    }
+
+    fn source(&self) -> DisplayCow<'_> {
+        DisplayCow::Owned(Box::new(ObjectSource::new(self)))
+    }
+
+    fn type_name(&self) -> &'static str {
+        "object"
+    }
+
+    fn query_state(&self, state: State) -> bool {
+        matches!(state, State::Truthy)
+    }
+
+    fn to_kstr(&self) -> KStringCow<'_> {
+        KStringCow::from_string(ObjectRender::new(self).to_string())
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Object(
+            self.iter()
+                .map(|(k, v)| (k.into_owned(), v.to_value()))
+                .collect(),
+        )
+    }
+
+    fn as_object(&self) -> Option<&dyn ObjectView> {
+        Some(self)
+    }
+}
+
+/// only the item can be listed, the stack doesn't say what it has in it
+impl ObjectView for ItemScope<'_> {
+    fn as_value(&self) -> &dyn ValueView {
+        self
+    }
+
+    fn size(&self) -> i64 {
+        1
+    }
+
+    fn keys<'k>(&'k self) -> Box<dyn Iterator<Item = KStringCow<'k>> + 'k> {
+        Box::new(std::iter::once(KStringCow::from_ref(self.name)))
+    }
+
+    fn values<'k>(&'k self) -> Box<dyn Iterator<Item = &'k dyn ValueView> + 'k> {
+        Box::new(std::iter::once(self.item))
+    }
+
+    fn iter<'k>(&'k self) -> Box<dyn Iterator<Item = (KStringCow<'k>, &'k dyn ValueView)> + 'k> {
+        Box::new(std::iter::once((
+            KStringCow::from_ref(self.name),
+            self.item,
+        )))
+    }
+
+    fn contains_key(&self, index: &str) -> bool {
+        self.get(index).is_some()
+    }
+
+    fn get<'s>(&'s self, index: &str) -> Option<&'s dyn ValueView> {
+        if index == self.name {
+            return Some(self.item);
+        }
+        match self.stack.try_get(&[ScalarCow::new(index)])? {
+            ValueCow::Borrowed(value) => Some(value),
+            ValueCow::Owned(_) => None,
+        }
+    }
+}
+
+/// an expression rendered against each item with the item bound to the given name
+struct ItemExpression {
+    template: liquid::Template,
+    name: String,
+}
+
+impl ItemExpression {
+    /// `{% if expression %}` for `where_exp` and `find_exp`, `{{ expression }}` for
+    /// `group_by_exp`
+    fn new(
+        parser: &liquid::Parser,
+        kind: ExpKind,
+        args: &ExpArgs,
+        runtime: &Runtime,
+    ) -> Result<Self> {
+        let args = args.evaluate(runtime)?;
+        let template = match kind {
+            ExpKind::GroupBy => format!("{{{{ {} }}}}", args.expression),
+            _ => format!("{{% if {} %}}true{{% endif %}}", args.expression),
+        };
+        Ok(ItemExpression {
+            template: parser.parse(&template)?,
+            name: args.variable.to_string(),
+        })
+    }
+
+    fn render(&self, item: &dyn ValueView, runtime: &Runtime) -> Result<String> {
+        self.template.render(&ItemScope {
+            name: &self.name,
+            item,
+            stack: runtime.stack(),
+        })
+    }
+
+    fn is_true(&self, item: &dyn ValueView, runtime: &Runtime) -> Result<bool> {
+        Ok(self.render(item, runtime)? == "true")
+    }
+}
+
+/// `[{ name, items, size }]` in the order that each name is first found
+fn groups(groups: Vec<(String, Vec<Value>)>) -> Value {
+    Value::array(groups.into_iter().map(|(name, items)| {
+        Value::Object(liquid::object!({
+            "name": name,
+            "size": items.len() as i64,
+            "items": items,
+        }))
+    }))
+}
+
+fn add_to_group(groups: &mut Vec<(String, Vec<Value>)>, name: String, item: Value) {
+    match groups.iter_mut().find(|(n, _)| *n == name) {
+        Some((_, items)) => items.push(item),
+        None => groups.push((name, vec![item])),
+    }
+}
+
+#[derive(Debug, FilterParameters)]
+struct WhereArgs {
+    #[parameter(
+        description = "The property to match, `a.b` for nested ones",
+        arg_type = "str"
+    )]
+    property: Expression,
+    #[parameter(description = "The value the property should have", arg_type = "any")]
+    value: Option<Expression>,
+}
+
+#[derive(Clone, ParseFilter, FilterReflection)]
+#[filter(
+    name = "where",
+    description = "The items where the property has the value, or any of it's values for arrays",
+    parameters(WhereArgs),
+    parsed(WhereFilter)
+)]
+pub struct Where;
+
+#[derive(Debug, FromFilterParameters, Display_filter)]
+#[name = "where"]
+struct WhereFilter {
+    #[parameters]
+    args: WhereArgs,
+}
+
+impl Filter for WhereFilter {
+    fn evaluate(&self, input: &dyn ValueView, runtime: &Runtime) -> Result<Value> {
+        let args = self.args.evaluate(runtime)?;
+        let target = args.value.as_ref().map(|v| v.as_view());
+        Ok(Value::array(
+            as_items(input)
+                .into_iter()
+                .filter(|item| matches(property(*item, &args.property), target))
+                .map(|item| item.to_value()),
+        ))
+    }
+}
+
+#[derive(Clone, ParseFilter, FilterReflection)]
+#[filter(
+    name = "find",
+    description = "The first item where the property has the value, nil if there isn't one",
+    parameters(WhereArgs),
+    parsed(FindFilter)
+)]
+pub struct Find;
+
+#[derive(Debug, FromFilterParameters, Display_filter)]
+#[name = "find"]
+struct FindFilter {
+    #[parameters]
+    args: WhereArgs,
+}
+
+impl Filter for FindFilter {
+    fn evaluate(&self, input: &dyn ValueView, runtime: &Runtime) -> Result<Value> {
+        let args = self.args.evaluate(runtime)?;
+        let target = args.value.as_ref().map(|v| v.as_view());
+        Ok(as_items(input)
+            .into_iter()
+            .find(|item| matches(property(*item, &args.property), target))
+            .map_or(Value::Nil, |item| item.to_value()))
+    }
+}
+
+#[derive(Debug, FilterParameters)]
+struct GroupByArgs {
+    #[parameter(description = "The property to group by", arg_type = "str")]
+    property: Expression,
+}
+
+#[derive(Clone, ParseFilter, FilterReflection)]
+#[filter(
+    name = "group_by",
+    description = "Groups the items by a property into `name`, `items` and `size`",
+    parameters(GroupByArgs),
+    parsed(GroupByFilter)
+)]
+pub struct GroupBy;
+
+#[derive(Debug, FromFilterParameters, Display_filter)]
+#[name = "group_by"]
+struct GroupByFilter {
+    #[parameters]
+    args: GroupByArgs,
+}
+
+impl Filter for GroupByFilter {
+    fn evaluate(&self, input: &dyn ValueView, runtime: &Runtime) -> Result<Value> {
+        let args = self.args.evaluate(runtime)?;
+        let mut all = Vec::new();
+        for item in as_items(input) {
+            let name = property(item, &args.property)
+                .map(|v| v.to_kstr().to_string())
+                .unwrap_or_default();
+            add_to_group(&mut all, name, item.to_value());
+        }
+        Ok(groups(all))
+    }
+}
+
+#[derive(Debug, FilterParameters)]
+struct SortArgs {
+    #[parameter(description = "The property to sort by", arg_type = "str")]
+    property: Option<Expression>,
+    #[parameter(description = "Where nil goes, `first` or `last`", arg_type = "str")]
+    nils: Option<Expression>,
+}
+
+#[derive(Clone, ParseFilter, FilterReflection)]
+#[filter(
+    name = "sort",
+    description = "Sorts the items, or by a property of them. Nils are last unless `first` is given",
+    parameters(SortArgs),
+    parsed(SortFilter)
+)]
+pub struct Sort;
+
+#[derive(Debug, FromFilterParameters, Display_filter)]
+#[name = "sort"]
+struct SortFilter {
+    #[parameters]
+    args: SortArgs,
+}
+
+impl Filter for SortFilter {
+    fn evaluate(&self, input: &dyn ValueView, runtime: &Runtime) -> Result<Value> {
+        let args = self.args.evaluate(runtime)?;
+        let nils = match args.nils.as_deref() {
+            None | Some("last") => Ordering::Greater,
+            Some("first") => Ordering::Less,
+            Some(_) => return Err(invalid_argument("nils", "expected `first` or `last`")),
+        };
+        let key = |item: &Value| -> Option<Value> {
+            match &args.property {
+                Some(path) => property(item, path).map(|v| v.to_value()),
+                None if item.is_nil() => None,
+                None => Some(item.clone()),
+            }
+        };
+
+        let mut sorted: Vec<(Option<Value>, Value)> = as_items(input)
+            .into_iter()
+            .map(|item| item.to_value())
+            .map(|item| (key(&item), item))
+            .collect();
+        sorted.sort_by(|(a, _), (b, _)| match (a, b) {
+            (None, None) => Ordering::Equal,
+            (None, _) => nils,
+            (_, None) => nils.reverse(),
+            (Some(a), Some(b)) => ValueViewCmp::new(a)
+                .partial_cmp(&ValueViewCmp::new(b))
+                .unwrap_or(Ordering::Equal),
+        });
+        Ok(Value::array(sorted.into_iter().map(|(_, item)| item)))
+    }
+}
+
+#[derive(Debug, FilterParameters)]
+struct ExpArgs {
+    #[parameter(
+        description = "The name each item is given in the expression",
+        arg_type = "str"
+    )]
+    variable: Expression,
+    #[parameter(description = "The liquid expression", arg_type = "str")]
+    expression: Expression,
+}
+
+/// which of the `_exp` filters it is, they only differ in what's done with the items
+#[derive(Debug, Clone, Copy)]
+enum ExpKind {
+    Where,
+    Find,
+    GroupBy,
+}
+
+impl ExpKind {
+    fn name(self) -> &'static str {
+        match self {
+            ExpKind::Where => "where_exp",
+            ExpKind::Find => "find_exp",
+            ExpKind::GroupBy => "group_by_exp",
+        }
+    }
+
+    /// the expressions are parsed with `parser` as a template when the filter is used
+    fn parse(self, parser: &liquid::Parser, arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        Ok(Box::new(ExpFilter {
+            args: ExpArgs::from_args(arguments)?,
+            parser: parser.clone(),
+            kind: self,
+        }))
+    }
+}
+
+#[derive(Clone, FilterReflection)]
+#[filter(
+    name = "where_exp",
+    description = "The items where the expression is true e.g. `\"a\", \"a.draft != true\"`",
+    parameters(ExpArgs),
+    parsed(ExpFilter)
+)]
+pub struct WhereExp {
+    parser: liquid::Parser,
+}
+
+impl WhereExp {
+    pub fn new(parser: liquid::Parser) -> Self {
+        WhereExp { parser }
+    }
+}
+
+impl ParseFilter for WhereExp {
+    fn parse(&self, arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        ExpKind::Where.parse(&self.parser, arguments)
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self as &dyn FilterReflection
+    }
+}
+
+#[derive(Clone, FilterReflection)]
+#[filter(
+    name = "find_exp",
+    description = "The first item where the expression is true, nil if there isn't one",
+    parameters(ExpArgs),
+    parsed(ExpFilter)
+)]
+pub struct FindExp {
+    parser: liquid::Parser,
+}
+
+impl FindExp {
+    pub fn new(parser: liquid::Parser) -> Self {
+        FindExp { parser }
+    }
+}
+
+impl ParseFilter for FindExp {
+    fn parse(&self, arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        ExpKind::Find.parse(&self.parser, arguments)
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self as &dyn FilterReflection
+    }
+}
+
+#[derive(Clone, FilterReflection)]
+#[filter(
+    name = "group_by_exp",
+    description = "Groups the items by what the expression renders to e.g. `\"a\", \"a.date | slice: 0, 4\"`",
+    parameters(ExpArgs),
+    parsed(ExpFilter)
+)]
+pub struct GroupByExp {
+    parser: liquid::Parser,
+}
+
+impl GroupByExp {
+    pub fn new(parser: liquid::Parser) -> Self {
+        GroupByExp { parser }
+    }
+}
+
+impl ParseFilter for GroupByExp {
+    fn parse(&self, arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        ExpKind::GroupBy.parse(&self.parser, arguments)
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self as &dyn FilterReflection
+    }
+}
+
+struct ExpFilter {
+    args: ExpArgs,
+    parser: liquid::Parser,
+    kind: ExpKind,
+}
+
+impl std::fmt::Debug for ExpFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExpFilter")
+            .field("args", &self.args)
+            .field("kind", &self.kind)
+            .finish()
+    }
+}
+
+impl std::fmt::Display for ExpFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} : {}", self.kind.name(), &self.args)
+    }
+}
+
+impl Filter for ExpFilter {
+    fn evaluate(&self, input: &dyn ValueView, runtime: &Runtime) -> Result<Value> {
+        let expression = ItemExpression::new(&self.parser, self.kind, &self.args, runtime)?;
+        let items = as_items(input);
+        match self.kind {
+            ExpKind::Where => {
+                let mut found = Vec::new();
+                for item in items {
+                    if expression.is_true(item, runtime)? {
+                        found.push(item.to_value());
+                    }
+                }
+                Ok(Value::array(found))
+            }
+            ExpKind::Find => {
+                for item in items {
+                    if expression.is_true(item, runtime)? {
+                        return Ok(item.to_value());
+                    }
+                }
+                Ok(Value::Nil)
+            }
+            ExpKind::GroupBy => {
+                let mut all = Vec::new();
+                for item in items {
+                    let name = expression.render(item, runtime)?.trim().to_string();
+                    add_to_group(&mut all, name, item.to_value());
+                }
+                Ok(groups(all))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod array_filter_tests {
+    use super::*;
+    use crate::util::render_with;
+    use pretty_assertions::assert_eq;
+
+    fn render(template: &str) -> String {
+        let expressions = liquid::ParserBuilder::with_stdlib().build().unwrap();
+        let filters = |builder: liquid::ParserBuilder| {
+            builder
+                .filter(Where)
+                .filter(Find)
+                .filter(GroupBy)
+                .filter(Sort)
+                .filter(WhereExp::new(expressions.clone()))
+                .filter(FindExp::new(expressions.clone()))
+                .filter(GroupByExp::new(expressions))
+        };
+        render_with(
+            filters,
+            template,
+            &liquid::object!({
+                "page": { "title": "cats" },
+                "global": {
+                    "articles": [
+                        { "title": "cats", "tags": ["rust", "cats"], "date": "2020-01-03", "author": { "name": "a" } },
+                        { "title": "dogs", "tags": ["dogs"], "date": "2020-02-01", "author": { "name": "b" } },
+                        { "title": "birds", "tags": ["rust"], "date": "2019-12-25" },
+                        { "title": "fish", "tags": ["rust"], "date": "2020-01-20", "author": { "name": "a" } },
+                    ]
+                }
+            }),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn where_and_find() {
+        assert_eq!(
+            "cats birds fish",
+            render("{{ global.articles | where: 'tags', 'rust' | map: 'title' | join: ' ' }}")
+        );
+        assert_eq!(
+            "cats fish",
+            render("{{ global.articles | where: 'author.name', 'a' | map: 'title' | join: ' ' }}")
+        );
+        assert_eq!(
+            "dogs",
+            render("{% assign a = global.articles | find: 'tags', 'dogs' %}{{ a.title }}")
+        );
+        assert_eq!(
+            "",
+            render("{% assign a = global.articles | find: 'title', 'cows' %}{% if a %}found{% endif %}")
+        );
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(
+            "dogs birds fish",
+            render(
+                "{{ global.articles | where_exp: 'a', 'a.title != page.title' \
+                 | map: 'title' | join: ' ' }}"
+            )
+        );
+        assert_eq!(
+            "birds",
+            render("{% assign a = global.articles | find_exp: 'a', 'a.date < \"2020\"' %}{{ a.title }}")
+        );
+    }
+
+    #[test]
+    fn expressions_in_scope() {
+        assert_eq!(
+            "dogs",
+            render(
+                "{% assign t = 'dogs' %}\
+                 {{ global.articles | where_exp: 'a', 'a.tags contains t' | map: 'title' | join: ' ' }}"
+            )
+        );
+        assert_eq!(
+            "dogs: dogs rust: cats birds fish ",
+            render(
+                "{% assign tags = 'dogs,rust' | split: ',' %}{% for t in tags %}{{ t }}: \
+                 {{ global.articles | where_exp: 'a', 'a.tags contains t' | map: 'title' | join: ' ' }} \
+                 {% endfor %}"
+            )
+        );
+        // the item hides a variable with the same name
+        assert_eq!(
+            "cats",
+            render(
+                "{% assign a = 'x' %}\
+                 {% assign found = global.articles | find_exp: 'a', 'a.title == page.title' %}\
+                 {{ found.title }}"
+            )
+        );
+    }
+
+    #[test]
+    fn group() {
+        assert_eq!(
+            "a:2 b:1 :1 ",
+            render(
+                "{% assign groups = global.articles | group_by: 'author.name' %}\
+                 {% for g in groups %}{{ g.name }}:{{ g.size }} {% endfor %}"
+            )
+        );
+        // the rust articles in 2020 by month
+        assert_eq!(
+            "2020-01: cats fish ",
+            render(
+                "{% assign months = global.articles | where: 'tags', 'rust' \
+                 | where_exp: 'a', 'a.date contains \"2020\"' \
+                 | group_by_exp: 'a', 'a.date | slice: 0, 7' %}\
+                 {% for m in months %}{{ m.name }}: {{ m.items | map: 'title' | join: ' ' }} {% endfor %}"
+            )
+        );
+    }
+
+    #[test]
+    fn sort() {
+        assert_eq!(
+            "birds cats fish dogs",
+            render("{{ global.articles | sort: 'date' | map: 'title' | join: ' ' }}")
+        );
+        assert_eq!(
+            "cats fish dogs birds",
+            render("{{ global.articles | sort: 'author.name' | map: 'title' | join: ' ' }}")
+        );
+        assert_eq!(
+            "birds cats fish dogs",
+            render(
+                "{{ global.articles | sort: 'author.name', 'first' | map: 'title' | join: ' ' }}"
+            )
+        );
+        assert_eq!(
+            "1 2 3",
+            render("{{ '3,1,2' | split: ',' | sort | join: ' ' }}")
+        );
+    }
+}
@@ -1,9 +1,13 @@
 use crate::parse::{
-    parse_error_message, parse_key, parse_value_boolean, parse_value_list, parse_value_string,
-    parse_value_time, ParseError,
+    parse_error, parse_key, parse_value_boolean, parse_value_list, parse_value_number,
+    parse_value_string, parse_value_time, value_span,
 };
 
-use crate::error::CustomError;
+use crate::date_filter::liquid_date;
+use crate::error::{CustomError, Diagnostics, ErrorKind, Span, WarningKind};
+use crate::layout::{Chain, Layouts};
+use crate::markdown::{Markdown, MarkdownOverrides};
+use crate::toc::TocEntry;
 #[cfg(not(test))]
 use log::{debug, warn};
 
@@ -12,11 +16,11 @@ use std::println as warn;
 #[cfg(test)]
 use std::println as debug;
 
-use pulldown_cmark::{html, Options, Parser};
+use chrono::{FixedOffset, NaiveDateTime, TimeZone};
 use std::{
     fs::File,
     io::{BufRead, BufReader},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 #[derive(Debug, PartialEq)]
@@ -29,7 +33,14 @@ pub struct Config {
     pub categories: Vec<String>,
     pub tags: Vec<String>,
     pub visible: bool,
-    pub date: Option<String>,
+    pub date: Option<NaiveDateTime>,
+    /// the offset written after the date, otherwise it's in the site's timezone
+    pub date_offset: Option<FixedOffset>,
+    pub excerpt_separator: Option<String>,
+    pub markdown: MarkdownOverrides,
+    /// where `layout` and `base_layout` are in the front matter, so a missing layout can point at them
+    pub layout_span: Option<Span>,
+    pub base_layout_span: Option<Span>,
 }
 
 impl Default for Config {
@@ -44,6 +55,11 @@ impl Default for Config {
             tags: Vec::new(),
             visible: false,
             date: None,
+            date_offset: None,
+            excerpt_separator: None,
+            markdown: MarkdownOverrides::default(),
+            layout_span: None,
+            base_layout_span: None,
         }
     }
 }
@@ -52,8 +68,11 @@ impl Default for Config {
 pub struct Article {
     pub template: String,
     pub config: Config,
+    pub path: PathBuf,
     pub url: String,
     pub config_liquid: liquid::Object,
+    /// `layout_config` while the article renders, the front matter of it's layout
+    pub layout_liquid: liquid::Object,
     pub is_markdown: bool
 }
 
@@ -62,7 +81,7 @@ pub struct Article {
 /// BufReader<R> can improve the speed of programs that make small and repeated read calls to the same file or network socket.
 /// It does not help when reading very large amounts at once, or reading just one or a few times.
 /// It also provides no advantage when reading from a source that is already in memory, like a Vec<u8>.
-pub fn parse(data: BufReader<File>, path: &PathBuf) -> Result<(Config, String), ParseError> {
+pub fn parse(data: BufReader<File>, path: &Path) -> Result<(Config, String), CustomError> {
     let mut found_config = false;
     let mut line_n = 1;
     let mut config = Config::default();
@@ -77,14 +96,15 @@ pub fn parse(data: BufReader<File>, path: &PathBuf) -> Result<(Config, String),
     for line in lines {
         let line = match &line {
             Ok(line) => line,
-            Err(err) => Err(ParseError::InvalidValue(parse_error_message(
+            Err(err) => Err(parse_error(
+                ErrorKind::InvalidValue,
                 &err.to_string(),
                 path,
                 "",
                 0,
                 10,
                 line_n,
-            )))?,
+            ))?,
         };
         if !found_config && line == "---" {
             found_config = true;
@@ -94,19 +114,21 @@ pub fn parse(data: BufReader<File>, path: &PathBuf) -> Result<(Config, String),
             found_config = false;
             line_n += 1;
         } else if reached_end {
-            body += &line;
+            body += line;
             body += "\n";
         } else if found_config {
-            let (key, rest) = parse_key(&line, path, line, line_n)?;
+            let (key, rest) = parse_key(line, path, line, line_n)?;
             match key {
                 // match each thing but then need to work out how to map it....
                 // maybe look into the from string implementation???
                 "layout" => {
-                    config.layout = parse_value_string(rest.trim(), path, line, line_n)?.to_string()
+                    config.layout = parse_value_string(rest.trim(), path, line, line_n)?.to_string();
+                    config.layout_span = Some(value_span(line, rest.trim(), line_n));
                 }
                 "base_layout" => {
                     config.base_layout =
-                        parse_value_string(rest.trim(), path, line, line_n)?.to_string()
+                        parse_value_string(rest.trim(), path, line, line_n)?.to_string();
+                    config.base_layout_span = Some(value_span(line, rest.trim(), line_n));
                 }
                 "title" => {
                     config.title = parse_value_string(rest.trim(), path, line, line_n)?.to_string()
@@ -127,30 +149,48 @@ pub fn parse(data: BufReader<File>, path: &PathBuf) -> Result<(Config, String),
                     config.visible = parse_value_boolean(rest.trim(), path, line, line_n)?
                 }
                 "date" => {
-                    config.date =
-                        Some(parse_value_time(rest.trim(), path, line, line_n)?.to_string())
+                    let (date, offset) = parse_value_time(rest.trim(), path, line, line_n)?;
+                    config.date = Some(date);
+                    config.date_offset = offset;
+                }
+                "excerpt_separator" => {
+                    config.excerpt_separator =
+                        Some(parse_value_string(rest.trim(), path, line, line_n)?.to_string())
+                }
+                "tables" | "footnotes" | "strikethrough" | "tasklists" | "smart_punctuation"
+                | "heading_ids" | "heading_anchors" | "liquid_first" => {
+                    config.markdown.set(
+                        key,
+                        parse_value_boolean(rest.trim(), path, line, line_n)?,
+                    )
+                }
+                "toc_depth" => {
+                    config.markdown.toc_depth =
+                        Some(parse_value_number(rest.trim(), path, line, line_n)?)
                 }
                 _ => {
-                    return Err(ParseError::InvalidKey(parse_error_message(
+                    return Err(parse_error(
+                        ErrorKind::InvalidKey,
                         "unknown key",
                         path,
                         line,
                         0,
                         line.len() - 1,
                         line_n,
-                    )))
+                    ))
                 }
             }
             line_n += 1;
         } else {
-            return Err(ParseError::InvalidConfig(parse_error_message(
+            return Err(parse_error(
+                ErrorKind::InvalidConfig,
                 "configuration needs to start with '---' for the first line",
                 path,
                 line,
                 0,
                 line.len(),
                 line_n,
-            )));
+            ));
         }
     }
 
@@ -158,33 +198,53 @@ pub fn parse(data: BufReader<File>, path: &PathBuf) -> Result<(Config, String),
         config.title = match crate::util::path_file_name_to_string(path){
             //TODO: remove the extension properly!
             Ok(t) => t.replace(".md", "").replace(".markdown", ""),
-            Err(error) => Err(ParseError::InvalidValue(format!("No 'title' found so defaulted to using filename as title but failed to get the filename from {:?} as {:?} occured", path, error)))? 
+            Err(error) => Err(CustomError::new(ErrorKind::InvalidValue, "No 'title' found so defaulted to using filename as title but failed to get the filename").path(path).cause(error))?
         };
     }
 
     if line_n == 2 {
-        Err(ParseError::InvalidConfig(format!(
-            "empty config no key value pairs found in {:?}",
-            path
-        )))
+        Err(
+            CustomError::new(ErrorKind::InvalidConfig, "empty config no key value pairs found")
+                .path(path),
+        )
     } else if !reached_end {
-        Err(ParseError::InvalidConfig(
-            "no at '---' for the last line of the configuration".into(),
-        ))
+        Err(CustomError::new(
+            ErrorKind::InvalidConfig,
+            "no at '---' for the last line of the configuration",
+        )
+        .path(path))
     } else if config.title.is_empty() {
-        Err(ParseError::InvalidConfig(
-            "missing configuration 'title' field".into(),
-        ))
+        Err(
+            CustomError::new(ErrorKind::InvalidConfig, "missing configuration 'title' field")
+                .path(path),
+        )
     } else {
         Ok((config, body))
     }
 }
 
+impl Config {
+    /// `page.date` as a liquid date so it can be formatted, in the site's timezone
+    pub fn liquid_date(&self, timezone: &FixedOffset) -> liquid::model::Value {
+        let date = self.date.and_then(|date| {
+            self.date_offset
+                .unwrap_or(*timezone)
+                .from_local_datetime(&date)
+                .single()
+        });
+        match date {
+            Some(date) => {
+                liquid::model::Value::scalar(liquid_date(date.with_timezone(timezone)))
+            }
+            None => liquid::model::Value::Nil,
+        }
+    }
+}
+
 impl Article {
-    
     /// header is in a --- --- block with new lines
     /// the rest of the doc is template in markdown
-    pub fn parse(contents: BufReader<File>, path: &PathBuf, md: bool) -> Result<Article, ParseError> {
+    pub fn parse(contents: BufReader<File>, path: &Path, md: bool) -> Result<Article, CustomError> {
         // markdown parsing NOTE: we are assuming that we are dealing with markdown hear!!!
         let (config, content) = parse(contents, path)?;
 
@@ -195,24 +255,27 @@ impl Article {
         }
         .replace(" ", "%20");
 
-        let config_liquid = liquid::object!({
+        let mut config_liquid = liquid::object!({
             "title": config.title,
             "description": config.description,
             "tags": config.tags,
             "categories": config.categories,
-            "date": config.date,
             "config": liquid::object!({
                 "visible": config.visible,
                 "layout": config.layout,
             }),
             "url":url,
+            "path": path.to_string_lossy().to_string(),
         });
+        config_liquid.insert("date".into(), config.liquid_date(&FixedOffset::east(0)));
 
         Ok(Article {
             template: content.trim().to_string(),
             config,
+            path: path.to_path_buf(),
             url,
             config_liquid,
+            layout_liquid: liquid::Object::new(),
             is_markdown: md
         })
     }
@@ -222,53 +285,158 @@ impl Article {
         globals: &liquid::Object,
         site: &liquid::Object,
         liquid_parser: &liquid::Parser,
-        md: bool,
+        markdown: Option<&Markdown>,
     ) -> Result<Self, CustomError> {
         debug!("pre_render");
 
-        // hack do proper error handling!!!
-        debug!("pre_rendered template");
+        match markdown {
+            Some(markdown) if markdown.liquid_first(&self.config.markdown) => {
+                let template = self.render_liquid(&self.template, globals, site, liquid_parser)?;
+                let rendered = markdown.render(&template, &self.config.markdown);
+                self.config_liquid
+                    .insert("toc".into(), rendered.toc_to_liquid());
+                self.template = rendered.html;
+            }
+            Some(markdown) => {
+                // the liquid is protected from the markdown so it's rendered afterwards
+                let mut rendered = markdown.render(&self.template, &self.config.markdown);
+                self.render_toc(&mut rendered.toc, globals, site, liquid_parser)?;
+                self.config_liquid
+                    .insert("toc".into(), rendered.toc_to_liquid());
+                self.template =
+                    self.render_liquid(&rendered.html, globals, site, liquid_parser)?;
+            }
+            None => {
+                self.template = self.render_liquid(&self.template, globals, site, liquid_parser)?;
+            }
+        }
 
-        
-            
-        debug!("pre_rendered render");
+        debug!("pre_rendered html");
 
-        if md {
-            let parser = Parser::new_ext(&self.template, Options::empty());
+        Ok(self)
+    }
 
-            // Write to String buffer.
-            let mut template = String::new();
-            html::push_html(&mut template, parser);
-            self.template = template.replace("&quot;", "\"");
-        } 
+    fn render_liquid(
+        &self,
+        template: &str,
+        globals: &liquid::Object,
+        site: &liquid::Object,
+        liquid_parser: &liquid::Parser,
+    ) -> Result<String, CustomError> {
+        Ok(liquid_parser.parse(template)?.render(&liquid::object!({
+            "global": globals,
+            "page": self.config_liquid,
+            "layout": self.config.layout,
+            "layout_config": self.layout_liquid,
+            "site": site,
+            "content": template,
+        }))?)
+    }
 
-        self.template = liquid_parser
-            .parse(&self.template)?
-            .render(&liquid::object!({
-                "global": globals,
-                "page": self.config_liquid,
-                "layout": self.config.layout,
-                "site": site,
-                "content": self.template,
-            }))?;
+    /// the liquid in the headings' titles, it's rendered with the rest of the article but the
+    /// table of contents is made before that
+    fn render_toc(
+        &self,
+        toc: &mut [TocEntry],
+        globals: &liquid::Object,
+        site: &liquid::Object,
+        liquid_parser: &liquid::Parser,
+    ) -> Result<(), CustomError> {
+        for entry in toc {
+            if entry.title.contains('{') {
+                entry.title = self.render_liquid(&entry.title, globals, site, liquid_parser)?;
+            }
+            self.render_toc(&mut entry.children, globals, site, liquid_parser)?;
+        }
+        Ok(())
+    }
 
-        debug!("pre_rendered html");
+    /// the part of the template before the excerpt separator, the article can override the
+    /// site wide separator and if the separator isn't found the whole template is used
+    fn excerpt_source(&self, separator: &str) -> &str {
+        let separator = self
+            .config
+            .excerpt_separator
+            .as_deref()
+            .unwrap_or(separator);
 
+        if separator.is_empty() {
+            return &self.template;
+        }
 
-        self.config_liquid = liquid::object!({
-            "title": self.config.title,
-            "description": self.config.description,
-            "tags": self.config.tags,
-            "date": self.config.date,
-            "categories": self.config.categories,
-            "config": liquid::object!({
-                "visible": self.config.visible,
-                "layout": self.config.layout,
-            }),
-            "url":self.url,
-        });
+        match self.template.find(separator) {
+            Some(index) => &self.template[..index],
+            None => &self.template,
+        }
+    }
 
-        Ok(self)
+    /// renders the excerpt through markdown and liquid on it's own (so it's valid html)
+    /// and exposes it as `page.excerpt`
+    pub fn excerpt(
+        &mut self,
+        globals: &liquid::Object,
+        site: &liquid::Object,
+        liquid_parser: &liquid::Parser,
+        markdown: &Markdown,
+        layouts: &Layouts,
+        separator: &str,
+    ) -> Result<(), CustomError> {
+        self.layout_liquid = self.layout_front_matter(layouts);
+        let source = self.excerpt_source(separator);
+        let excerpt = if !self.is_markdown {
+            self.render_liquid(source, globals, site, liquid_parser)?
+        } else if markdown.liquid_first(&self.config.markdown) {
+            let source = self.render_liquid(source, globals, site, liquid_parser)?;
+            markdown.render(&source, &self.config.markdown).html
+        } else {
+            let excerpt = markdown.render(source, &self.config.markdown).html;
+            self.render_liquid(&excerpt, globals, site, liquid_parser)?
+        };
+
+        self.config_liquid
+            .insert("excerpt".into(), liquid::model::Value::scalar(excerpt));
+        Ok(())
+    }
+
+    /// dates without an offset are in the site's timezone rather than UTC
+    pub fn timezone(mut self, timezone: &FixedOffset) -> Self {
+        self.config_liquid
+            .insert("date".into(), self.config.liquid_date(timezone));
+        self
+    }
+
+    /// `page.path` is from the root of the site like jekyll, so it's the same wherever
+    /// mole is run from
+    pub fn relative_to(mut self, root: &Path) -> Self {
+        let path = self.path.strip_prefix(root).unwrap_or(&self.path);
+        self.config_liquid.insert(
+            "path".into(),
+            liquid::model::Value::scalar(path.to_string_lossy().to_string()),
+        );
+        self
+    }
+
+    /// the layouts the article uses have to exist, `layout: none` doesn't use any and
+    /// base_layout isn't used when the layout has a parent. Their chains have to be valid too
+    pub fn check_layouts(&self, layouts: &Layouts) -> Result<(), CustomError> {
+        let layout = &self.config.layout;
+        if layout == "none" {
+            return Ok(());
+        }
+        if !layout.is_empty() {
+            layouts.check(layout, &self.path, self.config.layout_span.as_ref())?;
+            if layouts.has_parent(layout) {
+                return self.layouts(layouts).map(|_| ());
+            }
+        }
+        if !self.config.base_layout.is_empty() {
+            layouts.check(
+                &self.config.base_layout,
+                &self.path,
+                self.config.base_layout_span.as_ref(),
+            )?;
+        }
+        self.layouts(layouts).map(|_| ())
     }
 
     fn render(
@@ -276,56 +444,116 @@ impl Article {
         globals: &liquid::Object,
         site: &liquid::Object,
         parser: &liquid::Parser,
+        layouts: &Layouts,
+        diagnostics: &Diagnostics,
     ) -> Result<String, CustomError> {
         debug!("render");
+        if self.config.layout == "none" {
+            return Ok(self.template.clone());
+        }
+
         // debug!("page: {}", serde_json::to_string_pretty(&liquid::ValueView::to_value(&self.config_liquid)).unwrap());
         // debug!("site: {}", serde_json::to_string_pretty(site).unwrap());
         // debug!("global: {}", serde_json::to_string_pretty(globals).unwrap());
         if self.config.layout == self.config.base_layout {
-            if self.config.layout == "default" {
-                warn!("{:?} {:?}",self.config.title,ParseError::InvalidValue("base_layout has a default value of 'default' therefore setting layout to 'default' could causes an infinite loop that would lead to a stackoverflow".into()))
+            let message = if self.config.layout == "default" {
+                "base_layout has a default value of 'default' therefore setting layout to 'default' could causes an infinite loop that would lead to a stackoverflow"
             } else {
-                warn!("{:?} {:?}",self.config.title,ParseError::InvalidValue("layout and base layout are the same which could cause an infinite loop that would lead to a stackoverflow".into()))
-            }
+                "layout and base layout are the same which could cause an infinite loop that would lead to a stackoverflow"
+            };
+            diagnostics.warn(
+                CustomError::new(ErrorKind::InvalidValue, message)
+                    .path(&self.path)
+                    .warning(WarningKind::LayoutLoop),
+            );
         }
 
-        let template = if self.config.base_layout.is_empty() {
-            if self.config.layout.is_empty() {
-                parser.parse(&format!("{{%- include '{0}' -%}}", self.config.layout))?
-            } else {
-                warn!("no base layout found and no layout found");
-                parser.parse(&self.template)?
-            }
-        } else {
-            parser.parse(&format!("{{%- include '{0}' -%}}", self.config.base_layout))?
-        };
+        let (chain, included) = self.layouts(layouts)?;
+        if chain.is_empty() {
+            warn!("no base layout found and no layout found");
+        }
 
-        Ok(template.render(&liquid::object!({
-            "global": globals,
-            "page": self.config_liquid,
-            "layout": self.config.layout,
-            "site": site,
-            "content": self.template,
-        }))?)
+        // inner most first with the result being the `content` of the next one
+        let mut content = self.template.clone();
+        for ((name, _), layout) in chain
+            .iter()
+            .zip(Layouts::front_matter(&chain))
+            .skip(included)
+        {
+            content = parser
+                .parse(&format!("{{%- include '{0}' -%}}", name))?
+                .render(&liquid::object!({
+                    "global": globals,
+                    "page": self.config_liquid,
+                    "layout": self.config.layout,
+                    "layout_config": layout,
+                    "site": site,
+                    "content": content,
+                }))?;
+        }
+        Ok(content)
+    }
+
+    /// `layout_config` for the article itself is the same as it is in it's layout, so
+    /// `{{ layout_config.title }}` is the same in both
+    fn layout_front_matter(&self, layouts: &Layouts) -> liquid::Object {
+        self.layouts(layouts)
+            .ok()
+            .and_then(|(chain, _)| Layouts::front_matter(&chain).into_iter().next())
+            .unwrap_or_default()
+    }
+
+    /// the layouts the article is rendered with, inner most first. A layout with a parent is
+    /// rendered jekyll style and base_layout isn't used, otherwise base_layout is rendered and
+    /// it includes the layout itself. The layout is still at the start of the chain so that
+    /// it's front matter is part of `layout_config`, the number returned is how many aren't rendered
+    fn layouts<'a>(
+        &'a self,
+        layouts: &'a Layouts,
+    ) -> Result<(Chain<'a>, usize), CustomError> {
+        let layout = self.config.layout.as_str();
+        let base_layout = self.config.base_layout.as_str();
+        if layout == "none" {
+            return Ok((Vec::new(), 0));
+        }
+        if base_layout.is_empty() || layouts.has_parent(layout) {
+            return Ok((layouts.chain(layout)?, 0));
+        }
+
+        let mut chain = layouts.chain(base_layout)?;
+        if chain.iter().any(|(name, _)| *name == layout) {
+            return Ok((chain, 0));
+        }
+        match layouts.chain(layout)?.first() {
+            Some(first) => {
+                chain.insert(0, *first);
+                Ok((chain, 1))
+            }
+            None => Ok((chain, 0)),
+        }
     }
 
     pub fn true_render(
-        self,
+        mut self,
         global: &liquid::Object,
         site: &liquid::Object,
         parser: &liquid::Parser,
+        markdown: &Markdown,
+        layouts: &Layouts,
+        diagnostics: &Diagnostics,
     ) -> Result<String, CustomError> {
+        self.layout_liquid = self.layout_front_matter(layouts);
         if self.is_markdown {
             Ok(self
-                .pre_render(global, site, parser, true)?
-                .pre_render(global, site, parser, false  )?
-                .render(global, site, parser)?)
+                .pre_render(global, site, parser, Some(markdown))?
+                .pre_render(global, site, parser, None)?
+                .render(global, site, parser, layouts, diagnostics)?)
         }else{
             //TODO: do we need to do this twice still???
             Ok(self
-                .pre_render(global, site, parser, false)?
-                .pre_render(global, site, parser, false)?
-                .render(global, site, parser)?)
+                .pre_render(global, site, parser, None)?
+                .pre_render(global, site, parser, None)?
+                .render(global, site, parser, layouts, diagnostics)?)
         }
     }
 }
@@ -334,15 +562,16 @@ impl Article {
 mod render {
 
     use crate::include_tag::IncludeTag;
+    use crate::layout::{parse_layout, Layouts};
 
-    use super::{Article, BufReader, CustomError, File, ParseError};
+    use super::{Article, BufReader, CustomError, Diagnostics, ErrorKind, File, Markdown};
     use std::io::Write;
     use tempfile;
 
     // lazy didn't know how best to grab the type
     type Partials = liquid::partials::EagerCompiler<liquid::partials::InMemorySource>;
 
-    fn create_article(md: &str, path: &str) -> Result<Article, ParseError> {
+    fn create_article(md: &str, path: &str) -> Result<Article, CustomError> {
         // create a temp file
         let mut f = tempfile::Builder::new()
             .rand_bytes(0)
@@ -353,11 +582,11 @@ mod render {
 
         write!(f, "{}", md).unwrap();
 
-        Ok(Article::parse(
+        Article::parse(
             BufReader::new(File::open(path).unwrap()),
             &std::path::PathBuf::from(path),
-            true
-        )?)
+            true,
+        )
     }
 
     fn gen_render_mocks(
@@ -369,29 +598,45 @@ mod render {
         let a = create_article(md, path).unwrap();
         // create partials
         let mut source = Partials::empty();
+        let mut layouts = Layouts::default();
         for (k, v) in mocks {
-            source.add(k, v);
+            let (layout, body) = parse_layout(&v, std::path::Path::new(&k)).unwrap();
+            source.add(&k, body);
+            layouts.insert(k, layout);
         }
 
         let parser = liquid::ParserBuilder::with_stdlib()
             .partials(source)
-            .tag(IncludeTag)
+            .tag(IncludeTag::default())
             .build()
             .unwrap();
 
-        a.true_render(global, &liquid::object!({}), &parser)
+        a.true_render(
+            global,
+            &liquid::object!({}),
+            &parser,
+            &Markdown::default(),
+            &layouts,
+            &Diagnostics::default(),
+        )
     }
 
     mod parse_tests {
         use super::*;
+        use chrono::FixedOffset;
+        use liquid::ValueView;
         use pretty_assertions::assert_eq;
 
         #[test]
         fn empty_content() {
             assert_eq!(
-                Some(ParseError::InvalidConfig(
-                    "no at '---' for the last line of the configuration".into()
-                )),
+                Some(
+                    CustomError::new(
+                        ErrorKind::InvalidConfig,
+                        "no at '---' for the last line of the configuration"
+                    )
+                    .path(std::path::Path::new("empty_content"))
+                ),
                 create_article("", "empty_content").err()
             );
         }
@@ -447,6 +692,44 @@ mod render {
             assert_eq!("page", a.config.layout);
         }
 
+        #[test]
+        fn parse_date_timezone() {
+            let a: Article = create_article(
+                "---\ntitle: cats\ndate: 2020-01-03 10:00\n---\ncat",
+                "parse_date_timezone",
+            )
+            .unwrap()
+            .timezone(&FixedOffset::east(3600));
+            assert_eq!(
+                "2020-01-03 10:00:00 +0100",
+                a.config_liquid.get("date").unwrap().to_kstr().as_str()
+            );
+
+            let a: Article = create_article(
+                "---\ntitle: cats\ndate: 2020-01-03 10:00 -05:00\n---\ncat",
+                "parse_date_offset",
+            )
+            .unwrap()
+            .timezone(&FixedOffset::east(3600));
+            assert_eq!(
+                "2020-01-03 16:00:00 +0100",
+                a.config_liquid.get("date").unwrap().to_kstr().as_str()
+            );
+        }
+
+        #[test]
+        fn parse_relative_path() {
+            let root = std::env::current_dir().unwrap();
+            let mut a: Article =
+                create_article("---\ntitle: cats\n---\ncat", "parse_relative_path").unwrap();
+            a.path = root.join("_articles").join("cats.md");
+            let a = a.relative_to(&root);
+            assert_eq!(
+                std::path::Path::new("_articles").join("cats.md").to_string_lossy(),
+                a.config_liquid.get("path").unwrap().to_kstr().as_str()
+            );
+        }
+
         #[test]
         fn more_than_three_dashes() {
             let e = create_article(
@@ -456,10 +739,10 @@ mod render {
             .err();
             assert!(e != None, "no error found");
             match e {
-                Some(ParseError::InvalidConfig(config)) => {
+                Some(CustomError { kind: ErrorKind::InvalidConfig, message: config, .. }) => {
                     assert!(config.contains("configuration needs to start with '---' for the first line"), "expected string to end with 'configuration needs to start with '---' for the first line' found {}", config)
                 }
-                _ => assert!(false, "looking for ParseError::InvalidConfig found {:?}", e)
+                _ => assert!(false, "looking for ErrorKind::InvalidConfig found {:?}", e)
             }
         }
     }
@@ -555,6 +838,122 @@ mod render {
             );
         }
 
+        #[test]
+        fn render_markdown_overrides() {
+            assert_eq!(
+                "<p>~~cat~~ ~~dog~~</p>\n".to_string(),
+                gen_render_mocks(
+                    "---\r\nlayout: page\r\ntitle:cats and dogs\r\nstrikethrough: false\n---\r\n~~cat~~ ~~dog~~",
+                    "render_markdown_overrides",
+                    vec![("default".to_string(), "{{content}}".to_string())],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn render_layout_loop_warning() {
+            let diagnostics = Diagnostics::default();
+            let parser = liquid::ParserBuilder::with_stdlib()
+                .partials({
+                    let mut source = Partials::empty();
+                    source.add("page", "{{content}}");
+                    source
+                })
+                .tag(IncludeTag::new())
+                .build()
+                .unwrap();
+            create_article(
+                "---\r\nlayout: page\r\nbase_layout: page\r\ntitle:cats and dogs\n---\r\ncat",
+                "render_layout_loop_warning",
+            )
+            .unwrap()
+            .true_render(
+                &liquid::object!({}),
+                &liquid::object!({}),
+                &parser,
+                &Markdown::default(),
+                &Layouts::default(),
+                &diagnostics,
+            )
+            .unwrap();
+
+            let warnings = diagnostics.take();
+            assert_eq!(1, warnings.len());
+            assert_eq!(
+                crate::error::Severity::Warning(crate::error::WarningKind::LayoutLoop),
+                warnings[0].severity
+            );
+        }
+
+        #[test]
+        fn render_liquid_in_markdown() {
+            assert_eq!(
+                "<b>\"cat\"</b>\n<pre><code>&lt;a href=&quot;dog&quot;&gt;\n</code></pre>\n".to_string(),
+                gen_render_mocks(
+                    "---\r\nlayout: page\r\ntitle:cats and dogs\n---\r\n{% include 'quote' who=\"cat\" %}\n\n```\n<a href=\"dog\">\n```",
+                    "render_liquid_in_markdown",
+                    vec![
+                        ("default".to_string(), "{{content}}".to_string()),
+                        ("quote".to_string(), "<b>\"{{include.who}}\"</b>".to_string())
+                    ],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn render_liquid_first() {
+            assert_eq!(
+                "<p><em>cat</em></p>\n".to_string(),
+                gen_render_mocks(
+                    "---\r\nlayout: page\r\ntitle:cats and dogs\r\nliquid_first: true\n---\r\n{% include 'stars' %}",
+                    "render_liquid_first",
+                    vec![
+                        ("default".to_string(), "{{content}}".to_string()),
+                        ("stars".to_string(), "*cat*".to_string())
+                    ],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn render_toc() {
+            assert_eq!(
+                "<ul class=\"toc\"><li><a href=\"#cats\">cats</a></li></ul> 0".to_string(),
+                gen_render_mocks(
+                    "---\r\nlayout: page\r\ntitle:cats and dogs\r\ntoc_depth: 2\n---\r\n## cats\n### dogs",
+                    "render_toc",
+                    vec![(
+                        "default".to_string(),
+                        "{{page.toc.html}} {{page.toc.items[0].children | size}}".to_string()
+                    )],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn render_toc_liquid() {
+            assert_eq!(
+                "<ul class=\"toc\"><li><a href=\"#section\">cats and dogs</a></li>\
+                 <li><a href=\"#notes\">cats and dogs notes</a></li></ul>"
+                    .to_string(),
+                gen_render_mocks(
+                    "---\r\nlayout: page\r\ntitle:cats and dogs\n---\r\n## {{ page.title }}\n## {{ page.title }} notes",
+                    "render_toc_liquid",
+                    vec![("default".to_string(), "{{page.toc.html}}".to_string())],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
         #[test]
         fn render_chained_includes() {
             assert_eq!(
@@ -581,6 +980,196 @@ mod render {
             );
         }
 
+        #[test]
+        fn render_layout_none() {
+            assert_eq!(
+                "<p>cat</p>\n".to_string(),
+                gen_render_mocks(
+                    "---\r\nlayout: none\r\ntitle:cats and dogs\n---\r\ncat",
+                    "render_layout_none",
+                    vec![("default".to_string(), "dogs {{content}}".to_string())],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn check_layouts() {
+            let mut layouts = Layouts::default();
+            for (name, content) in &[
+                ("default", ""),
+                ("page", ""),
+                ("post", "---\nlayout: page\n---\n"),
+            ] {
+                let (layout, _) = parse_layout(content, std::path::Path::new(name)).unwrap();
+                layouts.insert(name.to_string(), layout);
+            }
+
+            let e = create_article(
+                "---\nlayout: pgae\ntitle:cats\n---\ncat",
+                "check_layouts_typo",
+            )
+            .unwrap()
+            .check_layouts(&layouts)
+            .unwrap_err();
+            assert_eq!("layout 'pgae' not found", e.message);
+            assert_eq!(Some(String::from("did you mean `page`?")), e.help);
+            let span = e.span.unwrap();
+            assert_eq!((2, 8, 12), (span.line, span.start, span.end));
+
+            let e = create_article(
+                "---\nlayout: page\nbase_layout: nope\ntitle:cats\n---\ncat",
+                "check_layouts_base",
+            )
+            .unwrap()
+            .check_layouts(&layouts)
+            .unwrap_err();
+            assert_eq!("layout 'nope' not found", e.message);
+            assert_eq!(3, e.span.unwrap().line);
+
+            for md in &[
+                "---\nlayout: none\nbase_layout: nope\ntitle:cats\n---\ncat",
+                "---\nlayout: post\nbase_layout: nope\ntitle:cats\n---\ncat",
+                "---\nlayout: page\ntitle:cats\n---\ncat",
+            ] {
+                assert!(create_article(md, "check_layouts_ok")
+                    .unwrap()
+                    .check_layouts(&layouts)
+                    .is_ok());
+            }
+        }
+
+        #[test]
+        fn render_layout_chain() {
+            assert_eq!(
+                "<html>blue <main>cats and dogs <p>cat</p>\n</main></html>".to_string(),
+                gen_render_mocks(
+                    "---\r\nlayout: post\r\ntitle:cats and dogs\n---\r\ncat",
+                    "render_layout_chain",
+                    vec![
+                        (
+                            "default".to_string(),
+                            "---\ncolour: red\n---\n<html>{{layout_config.colour}} {{content}}</html>".to_string()
+                        ),
+                        (
+                            "post".to_string(),
+                            "---\nlayout: default\ncolour: blue\n---\n<main>{{page.title}} {{content}}</main>".to_string()
+                        )
+                    ],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn render_layout_front_matter_in_article() {
+            // `layout_config` is the same object in the article as it is in it's layout
+            assert_eq!(
+                "<html>blue <main>blue <p>cat blue</p>\n</main></html>".to_string(),
+                gen_render_mocks(
+                    "---\r\nlayout: post\r\ntitle:cats and dogs\n---\r\ncat {{ layout_config.colour }}",
+                    "render_layout_front_matter_in_article",
+                    vec![
+                        (
+                            "default".to_string(),
+                            "---\ncolour: red\n---\n<html>{{layout_config.colour}} {{content}}</html>".to_string()
+                        ),
+                        (
+                            "post".to_string(),
+                            "---\nlayout: default\ncolour: blue\n---\n<main>{{layout_config.colour}} {{content}}</main>".to_string()
+                        )
+                    ],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn render_layout_name() {
+            // `layout` stays the name of the article's layout next to `layout_config`
+            assert_eq!(
+                "<html>post red <main>post <p>cat post red</p>\n</main></html>".to_string(),
+                gen_render_mocks(
+                    "---\r\nlayout: post\r\ntitle:cats and dogs\n---\r\ncat {{ layout }} {{ layout_config.colour }}",
+                    "render_layout_name",
+                    vec![
+                        (
+                            "default".to_string(),
+                            "---\ncolour: red\n---\n<html>{{layout}} {{layout_config.colour}} {{content}}</html>".to_string()
+                        ),
+                        (
+                            "post".to_string(),
+                            "---\nlayout: default\ncolour: red\n---\n<main>{{layout}} {{content}}</main>".to_string()
+                        )
+                    ],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn render_single_layout_front_matter() {
+            assert_eq!(
+                "<html>red <p>cat</p>\n</html>".to_string(),
+                gen_render_mocks(
+                    "---\r\ntitle:cats and dogs\n---\r\ncat",
+                    "render_single_layout_front_matter",
+                    vec![(
+                        "default".to_string(),
+                        "---\ncolour: red\n---\n<html>{{layout_config.colour}} {{content}}</html>".to_string()
+                    )],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn render_base_layout_chain() {
+            assert_eq!(
+                "<html>dark red <main>red <p>cat</p>\n</main></html>".to_string(),
+                gen_render_mocks(
+                    "---\r\nlayout: page\r\ntitle:cats and dogs\n---\r\ncat",
+                    "render_base_layout_chain",
+                    vec![
+                        (
+                            "html".to_string(),
+                            "---\ntheme: dark\n---\n<html>{{layout_config.theme}} {{layout_config.colour}} {{content}}</html>".to_string()
+                        ),
+                        (
+                            "default".to_string(),
+                            "---\nlayout: html\n---\n<main>{% include page.config.layout %}</main>".to_string()
+                        ),
+                        (
+                            "page".to_string(),
+                            "---\ncolour: red\n---\n{{layout_config.colour}} {{content}}".to_string()
+                        )
+                    ],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn render_layout_chain_loop() {
+            let e = gen_render_mocks(
+                "---\r\nlayout: post\r\ntitle:cats and dogs\n---\r\ncat",
+                "render_layout_chain_loop",
+                vec![
+                    ("default".to_string(), "---\nlayout: post\n---\n{{content}}".to_string()),
+                    ("post".to_string(), "---\nlayout: default\n---\n{{content}}".to_string()),
+                ],
+                &liquid::object!({}),
+            )
+            .unwrap_err();
+            assert_eq!("layout loop: default -> post -> default", e.message);
+        }
+
         #[test]
         fn render_template_jekyll() {
             assert_eq!(
@@ -603,4 +1192,97 @@ mod render {
             );
         }
     }
+
+    mod excerpts {
+        use super::*;
+        use liquid::ValueView;
+        use pretty_assertions::assert_eq;
+
+        fn gen_excerpt(md: &str, path: &str, separator: &str) -> String {
+            gen_excerpt_with(md, path, separator, &Layouts::default())
+        }
+
+        fn gen_excerpt_with(md: &str, path: &str, separator: &str, layouts: &Layouts) -> String {
+            let mut a = create_article(md, path).unwrap();
+            let parser = liquid::ParserBuilder::with_stdlib().build().unwrap();
+            a.excerpt(
+                &liquid::object!({}),
+                &liquid::object!({}),
+                &parser,
+                &Markdown::default(),
+                layouts,
+                separator,
+            )
+            .unwrap();
+            a.config_liquid.get("excerpt").unwrap().to_kstr().to_string()
+        }
+
+        #[test]
+        fn excerpt_layout_front_matter() {
+            let mut layouts = Layouts::default();
+            let (layout, _) = parse_layout(
+                "---\ncolour: red\n---\n{{ content }}",
+                std::path::Path::new("page"),
+            )
+            .unwrap();
+            layouts.insert(String::from("page"), layout);
+            assert_eq!(
+                "<p>cat red</p>\n",
+                gen_excerpt_with(
+                    "---\nlayout:page\ntitle:mole\n---\ncat {{ layout_config.colour }}\n\ndog",
+                    "excerpt_layout_front_matter",
+                    "\n\n",
+                    &layouts
+                )
+            );
+        }
+
+        #[test]
+        fn excerpt_first_paragraph() {
+            assert_eq!(
+                "<p>cat mole</p>\n",
+                gen_excerpt(
+                    "---\nlayout:page\ntitle:mole\n---\ncat {{page.title}}\n\ndog",
+                    "excerpt_first_paragraph",
+                    "\n\n"
+                )
+            );
+        }
+
+        #[test]
+        fn excerpt_separator() {
+            assert_eq!(
+                "<p>cat</p>\n<p><em>dog</em></p>\n",
+                gen_excerpt(
+                    "---\nlayout:page\ntitle:mole\n---\ncat\n\n*dog*\n<!--more-->\nbird",
+                    "excerpt_separator",
+                    "<!--more-->"
+                )
+            );
+        }
+
+        #[test]
+        fn excerpt_separator_front_matter() {
+            assert_eq!(
+                "<p>cat</p>\n<p>dog</p>\n",
+                gen_excerpt(
+                    "---\nlayout:page\ntitle:mole\nexcerpt_separator: <!--more-->\n---\ncat\n\ndog\n<!--more-->\nbird",
+                    "excerpt_separator_front_matter",
+                    "\n\n"
+                )
+            );
+        }
+
+        #[test]
+        fn excerpt_separator_missing() {
+            assert_eq!(
+                "<p>cat</p>\n<p>dog</p>\n",
+                gen_excerpt(
+                    "---\nlayout:page\ntitle:mole\n---\ncat\n\ndog",
+                    "excerpt_separator_missing",
+                    "<!--more-->"
+                )
+            );
+        }
+    }
 }
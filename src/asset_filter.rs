@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use liquid::ValueView;
+use liquid_core::{Display_filter, Filter, FilterReflection, ParseFilter, Result, Value};
+
+use crate::link_tag::not_found;
+use crate::url_filter::relative_url;
+
+/// fnv-1a, it doesn't need to be secure just the same from one build (and rust version) to
+/// the next so the urls only change when the file does
+pub fn hash(contents: &[u8]) -> u64 {
+    contents.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// `css/main.css` to `css/main.3f9a1c2b.css`
+pub fn fingerprint(name: &Path, contents: &[u8]) -> PathBuf {
+    let hash = format!("{:016x}", hash(contents));
+    let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match name.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, &hash[..8], extension.to_string_lossy()),
+        None => format!("{}.{}", stem, &hash[..8]),
+    };
+    name.with_file_name(file_name)
+}
+
+/// files that browsers, crawlers and hosts ask for by name so they have to keep it
+const WELL_KNOWN: &[&str] = &[
+    "robots.txt",
+    "humans.txt",
+    "ads.txt",
+    "favicon.ico",
+    "apple-touch-icon.png",
+    "browserconfig.xml",
+    "site.webmanifest",
+    "sitemap.xml",
+    "CNAME",
+    ".nojekyll",
+];
+
+/// whether the name gets a fingerprint, only files with one of the `[assets] extensions` do and
+/// never the well known files at the root of the site or anything in `.well-known/`
+pub fn is_fingerprinted(name: &Path, extensions: &[String]) -> bool {
+    if WELL_KNOWN.iter().any(|file| name == Path::new(file)) || name.starts_with(".well-known") {
+        return false;
+    }
+    name.extension().is_some_and(|extension| {
+        extensions
+            .iter()
+            .any(|e| extension.eq_ignore_ascii_case(e.trim_start_matches('.')))
+    })
+}
+
+/// the path as it would be in a url, with `/` on every platform
+pub fn url_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// every asset's name to the name it was written with, so `main.css` to `main.3f9a1c2b.css`
+pub type Assets = BTreeMap<String, String>;
+
+#[derive(Clone, FilterReflection)]
+#[filter(
+    name = "asset_url",
+    description = "The url of a stylesheet or file with it's fingerprint e.g. main.3f9a1c2b.css",
+    parsed(AssetUrlFilter)
+)]
+pub struct AssetUrl {
+    assets: Arc<Assets>,
+    baseurl: String,
+}
+
+impl AssetUrl {
+    pub fn new(assets: Arc<Assets>, baseurl: &str) -> Self {
+        AssetUrl {
+            assets,
+            baseurl: baseurl.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Display_filter)]
+#[name = "asset_url"]
+struct AssetUrlFilter {
+    assets: Arc<Assets>,
+    baseurl: String,
+}
+
+impl ParseFilter for AssetUrl {
+    fn parse(&self, _arguments: liquid_core::parser::FilterArguments) -> Result<Box<dyn Filter>> {
+        Ok(Box::new(AssetUrlFilter {
+            assets: self.assets.clone(),
+            baseurl: self.baseurl.clone(),
+        }))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self as &dyn FilterReflection
+    }
+}
+
+impl Filter for AssetUrlFilter {
+    fn evaluate(&self, input: &dyn ValueView, _runtime: &liquid_core::Runtime) -> Result<Value> {
+        let name = input.to_kstr();
+        let name = name.trim_start_matches('/');
+        match self.assets.get(name) {
+            Some(output) => Ok(Value::scalar(relative_url(&self.baseurl, output))),
+            None => Err(not_found("asset", name, self.assets.keys())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod asset_filter_tests {
+    use super::*;
+    use crate::util::render_with;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn fingerprints() {
+        let css = fingerprint(Path::new("blog/main.css"), b"a{color:red}");
+        assert_eq!(
+            css,
+            fingerprint(Path::new("blog/main.css"), b"a{color:red}")
+        );
+        assert_ne!(
+            css,
+            fingerprint(Path::new("blog/main.css"), b"a{color:blue}")
+        );
+        let name = url_path(&css);
+        assert!(name.starts_with("blog/main."), "{}", name);
+        assert!(name.ends_with(".css"), "{}", name);
+        assert_eq!("blog/main..css".len() + 8, name.len());
+        assert_eq!(
+            "LICENSE.af63dc4c",
+            url_path(&fingerprint(Path::new("LICENSE"), b"a"))
+        );
+    }
+
+    #[test]
+    fn fingerprinted() {
+        let extensions = vec![String::from("css"), String::from(".png")];
+        assert!(is_fingerprinted(Path::new("main.css"), &extensions));
+        assert!(is_fingerprinted(Path::new("img/cat.PNG"), &extensions));
+        assert!(!is_fingerprinted(Path::new("notes.txt"), &extensions));
+        assert!(!is_fingerprinted(Path::new("LICENSE"), &extensions));
+        assert!(!is_fingerprinted(
+            Path::new("apple-touch-icon.png"),
+            &extensions
+        ));
+        assert!(is_fingerprinted(
+            Path::new("img/apple-touch-icon.png"),
+            &extensions
+        ));
+        assert!(!is_fingerprinted(
+            Path::new(".well-known/logo.png"),
+            &extensions
+        ));
+    }
+
+    fn render(template: &str) -> Result<String> {
+        let mut assets = Assets::new();
+        assets.insert(String::from("main.css"), String::from("main.3f9a1c2b.css"));
+        assets.insert(String::from("cat.png"), String::from("cat.png"));
+        render_with(
+            |builder| builder.filter(AssetUrl::new(Arc::new(assets), "/docs")),
+            template,
+            &liquid::object!({}),
+        )
+    }
+
+    #[test]
+    fn asset_url() {
+        assert_eq!(
+            "/docs/main.3f9a1c2b.css /docs/cat.png",
+            render("{{ 'main.css' | asset_url }} {{ '/cat.png' | asset_url }}").unwrap()
+        );
+        let e = render("{{ 'mian.css' | asset_url }}")
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("requested asset=mian.css"), "{}", e);
+        assert!(e.contains("available assets=cat.png, main.css"), "{}", e);
+    }
+}
@@ -0,0 +1,513 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::error::{CustomError, ErrorKind, Span};
+use crate::util::suggestions;
+
+/// a `from: {% include 'foo' %}` line in a liquid error, `partial` is the name it resolved too
+/// which is missing when the include itself failed e.g. the partial doesn't exist
+struct Include<'a> {
+    statement: &'a str,
+    partial: Option<&'a str>,
+}
+
+/// liquid errors only know about templates by name and the line numbers they give are for the
+/// rendered html, so this works out which article, layout or include the error came from and
+/// finds the line by looking for the variable/filter/tag the error is about in that file.
+/// the `{% include %}` chain is added to the message as a backtrace, apart from the article
+/// itself which is returned separately e.g. `_articles/a.md (layout)` so that errors in
+/// templates are the same for every article that uses them
+pub fn map_liquid_error(
+    e: CustomError,
+    article: &Path,
+    templates: &HashMap<String, PathBuf>,
+    backtrace: bool,
+) -> (CustomError, Option<String>) {
+    if e.kind != ErrorKind::Liquid {
+        return (if e.path.is_none() { e.path(article) } else { e }, None);
+    }
+
+    let raw = e.message.clone();
+    let mut lines = raw.lines();
+    let first = lines.next().unwrap_or("");
+    let rest: Vec<&str> = lines.collect();
+
+    // syntax errors come from pest and already point at the line, just not in the right file
+    let (first, needle, context, includes) = match syntax_error(first, &rest) {
+        Some((message, tag)) => (message, Some((tag, false)), Vec::new(), Vec::new()),
+        None => {
+            let (context, includes) = split_trace(&rest);
+            (first.to_string(), needle(&context), context, includes)
+        }
+    };
+
+    // the error is in the inner most include that was actually found
+    let path = includes
+        .iter()
+        .find_map(|i| i.partial)
+        .and_then(|partial| templates.get(partial).cloned())
+        .unwrap_or_else(|| article.to_path_buf());
+
+    let mut message = first;
+    for line in &context {
+        message += "\n";
+        message += line;
+    }
+
+    // where each of the includes was included from, inner most first
+    let found: Vec<&Include> = includes.iter().filter(|i| i.partial.is_some()).collect();
+    let mut from_article = None;
+    for (i, include) in found.iter().enumerate() {
+        let (parent, is_article) = match found.get(i + 1).and_then(|p| templates.get(p.partial?)) {
+            Some(parent) => (parent.as_path(), false),
+            None => (article, true),
+        };
+        let line = read(parent)
+            .and_then(|source| find(&source, include.partial.unwrap_or(""), true))
+            .map(|span| span.line);
+
+        match (is_article, line) {
+            (true, Some(line)) => from_article = Some(format!("{}:{}", parent.display(), line)),
+            (true, None) => from_article = Some(format!("{} (layout)", parent.display())),
+            (false, Some(line)) => {
+                message += &format!(
+                    "\n  = included from {}:{} {}",
+                    parent.display(),
+                    line,
+                    include.statement
+                )
+            }
+            (false, None) => {
+                message += &format!(
+                    "\n  = included from {} {}",
+                    parent.display(),
+                    include.statement
+                )
+            }
+        }
+    }
+
+    let mut mapped = CustomError::new(ErrorKind::Liquid, message).path(&path);
+    if let Some(span) = needle.and_then(|(needle, word)| {
+        read(&path).and_then(|source| find_needle(&source, &needle, word, &context))
+    }) {
+        mapped = mapped.span(span);
+    }
+    if let Some(help) = did_you_mean(&context) {
+        mapped = mapped.help(help);
+    }
+    if backtrace {
+        mapped = mapped.cause(raw);
+    }
+    (mapped, from_article)
+}
+
+/// liquid lists what it could have been e.g. `available variables=global, page` so the
+/// closest of those to what was requested is suggested
+fn did_you_mean(context: &[&str]) -> Option<String> {
+    let mut requested = None;
+    let mut available = None;
+    for line in context {
+        if let Some((k, v)) = line.trim().split_once('=') {
+            if k.starts_with("requested ") {
+                requested = Some(v);
+            } else if k.starts_with("available ") {
+                available = Some(v);
+            }
+        }
+    }
+
+    let suggestions = suggestions(requested?, available?.split(", ").map(str::trim));
+    match suggestions.as_slice() {
+        [] => None,
+        [one] => Some(format!("did you mean `{}`?", one)),
+        many => Some(format!(
+            "did you mean one of {}?",
+            many.iter()
+                .map(|s| format!("`{}`", s))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )),
+    }
+}
+
+fn read(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path).ok()
+}
+
+/// pest errors look like `liquid:  --> 2:6` followed by the line, a `^---` under the column and
+/// then `= expected ...`. Returns the message and the liquid tag that the error points at
+fn syntax_error(first: &str, rest: &[&str]) -> Option<(String, String)> {
+    if !first
+        .trim_start_matches("liquid:")
+        .trim_start()
+        .starts_with("-->")
+    {
+        return None;
+    }
+
+    let mut text = None;
+    let mut column = None;
+    let mut message = "syntax error";
+    for line in rest {
+        let (gutter, content) = match line.split_once('|') {
+            Some(parts) => parts,
+            None => {
+                if let Some(m) = line.trim().strip_prefix("= ") {
+                    message = m;
+                }
+                continue;
+            }
+        };
+        let content = content.strip_prefix(' ').unwrap_or(content);
+        if !gutter.trim().is_empty() {
+            text = Some(content.trim_end_matches('␊'));
+        } else if let Some(col) = content.find('^') {
+            column = Some(col);
+        }
+    }
+
+    let (text, column) = (text?, column?);
+    let start = text
+        .get(..=column.min(text.len().saturating_sub(1)))?
+        .rfind('{')?;
+    let start = if start > 0 && text[..start].ends_with('{') {
+        start - 1
+    } else {
+        start
+    };
+    let close = if text[start..].starts_with("{{") {
+        "}}"
+    } else {
+        "%}"
+    };
+    let end = text[start..]
+        .find(close)
+        .map(|i| start + i + 2)
+        .unwrap_or(text.len());
+
+    Some((format!("liquid: {}", message), text[start..end].to_string()))
+}
+
+/// splits the rest of the error into the context of the inner most error e.g. `requested variable=foo`
+/// and the include chain
+fn split_trace<'a>(rest: &[&'a str]) -> (Vec<&'a str>, Vec<Include<'a>>) {
+    let mut context = Vec::new();
+    let mut includes: Vec<Include> = Vec::new();
+    for line in rest {
+        if let Some(statement) = line.strip_prefix("from: ") {
+            if statement.starts_with("{% include") || statement.starts_with("{%- include") {
+                includes.push(Include {
+                    statement,
+                    partial: None,
+                });
+                continue;
+            }
+        }
+
+        match includes.last_mut() {
+            Some(include) => {
+                if let Some((_, partial)) = line.trim().rsplit_once('=') {
+                    include.partial = Some(partial);
+                }
+            }
+            None if !line.trim().is_empty() => context.push(*line),
+            None => {}
+        }
+    }
+    (context, includes)
+}
+
+/// the thing to look for in the template, true if it should be matched as a whole word
+fn needle(context: &[&str]) -> Option<(String, bool)> {
+    for key in &[
+        "requested variable",
+        "requested index",
+        "requested filter",
+        "requested partial",
+        "requested tag",
+        "requested link",
+        "requested post",
+        "requested asset",
+        "requested image",
+        "filter",
+    ] {
+        for line in context {
+            if let Some((k, v)) = line.trim().split_once('=') {
+                if k == *key {
+                    return Some((v.to_string(), true));
+                }
+            }
+        }
+    }
+    None
+}
+
+fn find_needle(source: &str, needle: &str, word: bool, context: &[&str]) -> Option<Span> {
+    if word {
+        find_in_trace(source, needle, context).or_else(|| find(source, needle, false))
+    } else {
+        source.find(needle).map(|i| span(source, i, needle.len()))
+    }
+}
+
+/// the tags that liquid says the error came from, inner most first e.g. `from: {% if %}` or
+/// `filter=where_exp : "art", "art.tags contains t"`
+fn trace_tags<'a>(context: &[&'a str]) -> Vec<&'a str> {
+    context
+        .iter()
+        .filter_map(|line| {
+            let line = line.trim();
+            line.strip_prefix("from: ")
+                .or_else(|| line.strip_prefix("filter="))
+        })
+        .collect()
+}
+
+/// liquid writes tags back out from what it parsed so the spacing can be different to the file
+fn squash(text: &str) -> String {
+    text.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+/// `word` in the tag that failed, so a name that is also used earlier in the file
+/// e.g. `{% assign t %}` isn't pointed at instead
+fn find_in_trace(source: &str, word: &str, context: &[&str]) -> Option<Span> {
+    let blocks = blocks(source);
+    trace_tags(context)
+        .into_iter()
+        .map(squash)
+        .filter(|tag| !tag.is_empty())
+        .find_map(|tag| {
+            blocks
+                .iter()
+                .filter(|(start, end)| squash(&source[*start..*end + 2]).contains(&tag))
+                .find_map(|(start, end)| {
+                    find_word(&source[*start..*end], word)
+                        .map(|i| span(source, start + i, word.len()))
+                })
+        })
+}
+
+/// where each `{{ }}` and `{% %}` starts and where it's closing brackets are
+fn blocks(source: &str) -> Vec<(usize, usize)> {
+    let mut blocks = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = source[offset..].find('{').map(|i| offset + i) {
+        let close = match source[start..].get(..2) {
+            Some("{{") => "}}",
+            Some("{%") => "%}",
+            _ => {
+                offset = start + 1;
+                continue;
+            }
+        };
+        let end = match source[start + 2..].find(close) {
+            Some(end) => start + 2 + end,
+            None => break,
+        };
+        blocks.push((start, end));
+        offset = end;
+    }
+    blocks
+}
+
+/// the first time `word` appears in the block as a whole word
+fn find_word(block: &str, word: &str) -> Option<usize> {
+    let mut from = 0;
+    while let Some(i) = block[from..].find(word).map(|i| from + i) {
+        let before = block[..i].chars().next_back();
+        let after = block[i + word.len()..].chars().next();
+        if !is_word(before) && !is_word(after) {
+            return Some(i);
+        }
+        from = i + word.len();
+    }
+    None
+}
+
+/// the first time `word` appears inside of a `{{ }}` or `{% %}`, if `include` then only
+/// `{% include %}` tags are searched
+pub fn find(source: &str, word: &str, include: bool) -> Option<Span> {
+    if word.is_empty() {
+        return None;
+    }
+
+    blocks(source).into_iter().find_map(|(start, end)| {
+        let block = &source[start..end];
+        if include
+            && !block
+                .trim_start_matches(['{', '%', '-'])
+                .trim_start()
+                .starts_with("include")
+        {
+            return None;
+        }
+        find_word(block, word).map(|i| span(source, start + i, word.len()))
+    })
+}
+
+fn is_word(c: Option<char>) -> bool {
+    matches!(c, Some(c) if c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+fn span(source: &str, index: usize, len: usize) -> Span {
+    let line_start = source[..index].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line_end = source[index..]
+        .find('\n')
+        .map(|i| index + i)
+        .unwrap_or(source.len());
+    Span {
+        line: source[..line_start].matches('\n').count() + 1,
+        start: index - line_start,
+        end: (index - line_start + len).min(line_end - line_start),
+        text: source[line_start..line_end]
+            .trim_end_matches('\r')
+            .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod backtrace_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn files(files: &[(&str, &str)]) -> (tempfile::TempDir, HashMap<String, PathBuf>) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut templates = HashMap::new();
+        for (name, content) in files {
+            let path = dir.path().join(format!("{}.html", name));
+            std::fs::write(&path, content).unwrap();
+            templates.insert(name.to_string(), path);
+        }
+        (dir, templates)
+    }
+
+    #[test]
+    fn find_in_liquid() {
+        let source = "title\n{{ page.title }} {{ page.tilte | upcase }}";
+        let span = find(source, "tilte", false).unwrap();
+        assert_eq!(2, span.line);
+        assert_eq!(25, span.start);
+        assert_eq!(30, span.end);
+        assert!(find(source, "upper", false).is_none());
+        assert!(find("{{ page.title }}", "title", true).is_none());
+    }
+
+    #[test]
+    fn find_in_failing_tag() {
+        let source = "{% assign t = \"a\" %}\n{{ global.articles | where_exp: \"art\", \"art.tags contains t\" }}";
+        let context = [
+            "  with:",
+            "    requested variable=t",
+            "from: {% if art[\"tags\"] contains t %}",
+            "from: Filter error",
+            "  with:",
+            "    filter=where_exp : \"art\", \"art.tags contains t\"",
+        ];
+        let span = find_needle(source, "t", true, &context).unwrap();
+        assert_eq!((2, 58, 59), (span.line, span.start, span.end));
+        let span = find_needle(source, "t", true, &[]).unwrap();
+        assert_eq!((1, 10, 11), (span.line, span.start, span.end));
+    }
+
+    #[test]
+    fn error_in_include() {
+        let (dir, templates) = files(&[
+            ("default", "top\n{% include 'inner' %}\n"),
+            ("inner", "a\nb {{ page.tilte }}\n"),
+        ]);
+        let article = dir.path().join("a.md");
+        std::fs::write(&article, "---\ntitle: a\n---\nhello\n").unwrap();
+
+        let (e, from_article) = map_liquid_error(
+            CustomError::liquid("liquid: Unknown index\n  with:\n    variable=page\n    requested index=tilte\nfrom: {% include 'inner' %}\n  with:\n    'inner'=inner\nfrom: {% include 'default' %}\n  with:\n    'default'=default\n"),
+            &article,
+            &templates,
+            false,
+        );
+
+        assert_eq!(Some(templates["inner"].clone()), e.path);
+        assert!(e.help.is_none());
+        let span = e.span.unwrap();
+        assert_eq!((2, 10, 15), (span.line, span.start, span.end));
+        assert_eq!(
+            format!(
+                "liquid: Unknown index\n  with:\n    variable=page\n    requested index=tilte\n  = included from {}:2 {{% include 'inner' %}}",
+                templates["default"].display(),
+            ),
+            e.message
+        );
+        assert_eq!(
+            Some(format!("{} (layout)", article.display())),
+            from_article
+        );
+    }
+
+    #[test]
+    fn error_in_article() {
+        let (dir, templates) = files(&[]);
+        let article = dir.path().join("a.md");
+        std::fs::write(
+            &article,
+            "---\ntitle: a\n---\nhello {% include 'nothere' %}\n",
+        )
+        .unwrap();
+
+        let (e, from_article) = map_liquid_error(
+            CustomError::liquid("liquid: Unknown partial-template\n  with:\n    requested partial=nothere\nfrom: {% include 'nothere' %}\n"),
+            &article,
+            &templates,
+            true,
+        );
+        assert_eq!(Some(article), e.path);
+        assert_eq!(4, e.span.unwrap().line);
+        assert!(e.cause.is_some());
+        assert!(from_article.is_none());
+    }
+
+    #[test]
+    fn syntax_error_in_article() {
+        let (dir, templates) = files(&[]);
+        let article = dir.path().join("a.md");
+        std::fs::write(&article, "---\ntitle: a\n---\nhello\n{% if %}\n").unwrap();
+
+        let (e, _) = map_liquid_error(
+            CustomError::liquid(
+                "liquid:  --> 2:6\n  |\n2 | {% if %}</p>␊\n  |      ^---\n  |\n  = Value expected.",
+            ),
+            &article,
+            &templates,
+            false,
+        );
+        assert_eq!("liquid: Value expected.", e.message);
+        let span = e.span.unwrap();
+        assert_eq!((5, 0, 8), (span.line, span.start, span.end));
+    }
+
+    #[test]
+    fn suggestions_from_context() {
+        assert_eq!(
+            Some(String::from("did you mean `title`?")),
+            did_you_mean(&[
+                "  with:",
+                "    variable=page",
+                "    requested index=tilte",
+                "    available indexes=content, date, tags, title, url",
+            ])
+        );
+        assert_eq!(
+            Some(String::from("did you mean one of `page`, `site`?")),
+            did_you_mean(&[
+                "    requested variable=sage",
+                "    available variables=content, global, layout, page, site",
+            ])
+        );
+        assert_eq!(
+            None,
+            did_you_mean(&[
+                "    requested filter=not_a_filter",
+                "    available filters=abs, append, upcase",
+            ])
+        );
+    }
+}
@@ -13,7 +13,7 @@ use log::{error, info};
 use notify::{watcher, RecursiveMode, Watcher};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::process;
+use std::process::{self, ExitCode};
 use std::sync::mpsc::channel;
 use std::thread;
 use std::time::Duration;
@@ -24,16 +24,18 @@ use tiny_http::{Request, Response, Server};
 pub enum SubCommands {
     INIT(InitCommand),
     BUILD(BuildCommand),
+    CHECKLINKS(CheckLinksCommand),
     // CLEAN(CleanCommand),
     // NEW(NewCommand),
     // SERVE(ServeCommand)
 }
 
 impl SubCommands {
-    pub fn run(self) {
+    pub fn run(self) -> ExitCode {
         match self {
             SubCommands::INIT(x) => x.run(),
             SubCommands::BUILD(x) => x.run(),
+            SubCommands::CHECKLINKS(x) => x.run(),
         }
     }
 }
@@ -51,7 +53,7 @@ pub struct InitCommand {
 }
 
 impl InitCommand {
-    pub fn run(self) {
+    pub fn run(self) -> ExitCode {
         if self.version {
             info!("version: {:?}", env!("CARGO_PKG_VERSION"));
         }
@@ -60,23 +62,29 @@ impl InitCommand {
         if current.is_dir() {
             info!("init");
 
-        /* we need to write:
-            - _layouts
-            - _includes
-            - _articles
-            - _sources (although what is this actually meant to be for)
-            - _scss
-            - _output
-            - .mole.toml -> going to be used to identify the project (just so that clean is safer)
-        */
+            /* we need to write:
+                - _layouts
+                - _includes
+                - _articles
+                - _sources (although what is this actually meant to be for)
+                - _scss
+                - _output
+                - .mole.toml -> going to be used to identify the project (just so that clean is safer)
+            */
+            ExitCode::SUCCESS
         } else {
             error!("{:?} is not a directory so could not initailize", current);
+            ExitCode::FAILURE
         }
     }
 }
 
 // largely copied from cobalt-org/cobalt.rs/src/bin/serve.rs as it's under MIT
-fn static_file_handler(dest: &Path, req: Request) -> Result<(), mole::error::CustomError> {
+fn static_file_handler(
+    dest: &Path,
+    baseurl: &str,
+    req: Request,
+) -> Result<(), mole::error::CustomError> {
     // grab the requested path
     let mut req_path = req.url().to_string();
 
@@ -86,6 +94,32 @@ fn static_file_handler(dest: &Path, req: Request) -> Result<(), mole::error::Cus
         req_path.truncate(position);
     }
 
+    // everything is under the baseurl, the root redirects to it to make it easy to find
+    if !baseurl.is_empty() {
+        if req_path == "/" {
+            // headers have to be ascii so anything else in the baseurl is percent encoded
+            let location: String = baseurl
+                .bytes()
+                .map(|b| match b {
+                    b if b.is_ascii_graphic() => char::from(b).to_string(),
+                    b => format!("%{:02X}", b),
+                })
+                .collect();
+            req.respond(
+                match tiny_http::Header::from_bytes(&b"Location"[..], format!("{}/", location)) {
+                    Ok(header) => Response::empty(302).with_header(header),
+                    Err(_) => Response::empty(500),
+                },
+            )?;
+            return Ok(());
+        }
+        req_path = match req_path.strip_prefix(baseurl) {
+            Some("") => String::from("/"),
+            Some(rest) if rest.starts_with('/') => rest.to_string(),
+            _ => return not_found(req),
+        };
+    }
+
     // find the path of the file in the local system
     // (this gets rid of the '/' in `p`, so the `join()` will not replace the path)
     let path = dest.to_path_buf().join(Path::new(&req_path[1..]));
@@ -119,30 +153,40 @@ fn static_file_handler(dest: &Path, req: Request) -> Result<(), mole::error::Cus
             ),
         )?;
     } else {
-        req.respond(
-            Response::from_string("<h1>404 page, couldn't find the anything...</h1>")
-                .with_status_code(404)
-                .with_header(
-                    tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/html;"[..])
-                        .unwrap(),
-                ),
-        )?;
+        return not_found(req);
     }
 
     Ok(())
 }
 
+fn not_found(req: Request) -> Result<(), mole::error::CustomError> {
+    req.respond(
+        Response::from_string("<h1>404 page, couldn't find the anything...</h1>")
+            .with_status_code(404)
+            .with_header(
+                tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/html;"[..]).unwrap(),
+            ),
+    )?;
+    Ok(())
+}
+
 // largely copied from cobalt-org/cobalt.rs/src/bin/serve.rs as it's under MIT
-fn serve(dest: &Path, ip: &str) -> Result<(), mole::error::CustomError> {
+/// the site is served under `baseurl` so that links using `relative_url` work the same as
+/// they will when deployed e.g. http://127.0.0.1:4000/docs/
+fn serve(dest: &Path, ip: &str, baseurl: &str) -> Result<(), mole::error::CustomError> {
+    let baseurl = match baseurl.trim_matches('/') {
+        "" => String::new(),
+        baseurl => format!("/{}", baseurl),
+    };
     info!("Serving {:?} through static file server", dest);
-    info!("Server Listening on http://{}", &ip);
+    info!("Server Listening on http://{}{}/", &ip, baseurl);
     info!("Ctrl-c to stop the server");
 
     // attempts to create a server
-    let server = Server::http(ip).map_err(|e| mole::error::CustomError::IOError(e.to_string()))?;
+    let server = Server::http(ip).map_err(|e| mole::error::CustomError::io(e.to_string()))?;
 
     for request in server.incoming_requests() {
-        if let Err(e) = static_file_handler(&dest, request) {
+        if let Err(e) = static_file_handler(dest, &baseurl, request) {
             error!("{:?}", e);
         }
     }
@@ -180,9 +224,10 @@ pub struct BuildCommand {
     /// path from 'source' to articles folder
     scss: PathBuf,
 
-    #[argh(option, default = "PathBuf::from(\"_css/\")")]
-    /// path from 'source' to articles folder
-    scss_load_paths: PathBuf,
+    #[argh(option)]
+    /// path from 'source' to look for sass imports in, can be given more than once and
+    /// adds to `[sass] load_paths` in .mole.toml
+    scss_load_paths: Vec<PathBuf>,
 
     #[argh(switch)]
     /// whether or not to check the project for changes and if changed rebuild
@@ -197,12 +242,56 @@ pub struct BuildCommand {
     version: bool,
 
     #[argh(switch)]
-    /// shows full backtrace with file locations for liquid errors
+    /// also shows the original liquid error under the mapped one
     backtrace: bool,
+
+    #[argh(switch)]
+    /// warnings fail the build, see `[build] allow` in .mole.toml to ignore some of them
+    strict: bool,
+
+    #[argh(option)]
+    /// overrides `baseurl` in .mole.toml e.g. /docs, the server serves the site under it too
+    baseurl: Option<String>,
+
+    #[argh(switch)]
+    /// jekyll style includes, `include nav.html` is the file nav.html not a variable
+    jekyll_includes: bool,
+
+    #[argh(switch)]
+    /// checks every link in the output points at a file that exists after building
+    check_links: bool,
+
+    #[argh(switch)]
+    /// minifies the html even with `[build] minify = false`, ignored with --watch
+    minify: bool,
 }
 
 impl BuildCommand {
-    pub fn run(mut self) {
+    /// the command line flags take priority over .mole.toml
+    fn overrides(&self, config: &mut mole::config::SiteConfig) {
+        config.build.strict |= self.strict;
+        config.build.jekyll_includes |= self.jekyll_includes;
+        config.build.check_links |= self.check_links;
+        config.build.minify |= self.minify;
+        // rebuilds should be quick and the output readable while working on the site
+        if self.watch {
+            config.build.minify = false;
+        }
+        if let Some(baseurl) = &self.baseurl {
+            config.baseurl = baseurl.clone();
+        }
+    }
+
+    /// the flags then .mole.toml, all from the root of the site
+    fn load_paths(&self, current: &Path, config: &mole::config::SiteConfig) -> Vec<PathBuf> {
+        self.scss_load_paths
+            .iter()
+            .chain(&config.sass.load_paths)
+            .map(|path| current.join(path))
+            .collect()
+    }
+
+    pub fn run(mut self) -> ExitCode {
         if self.version {
             info!("version: {:?}", env!("CARGO_PKG_VERSION"));
         }
@@ -213,24 +302,42 @@ impl BuildCommand {
         self.articles = current.join(self.articles);
         self.scss = current.join(self.scss);
         if current.is_dir() {
+            let mut config = match mole::config::SiteConfig::load(current) {
+                Ok(config) => config,
+                Err(e) => {
+                    error!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            self.overrides(&mut config);
+            let baseurl = config.baseurl.clone();
+            let load_paths = self.load_paths(current, &config);
+            let load_paths: Vec<&Path> = load_paths.iter().map(PathBuf::as_path).collect();
+
+            if let Err(e) = fs::create_dir_all(&self.dest) {
+                error!("could not create {:?} {}", self.dest, e);
+                return ExitCode::FAILURE;
+            }
+
             info!("building");
-            mole::Build::new(&self.dest, self.backtrace)
+            let built = mole::Build::new(&self.dest, self.backtrace)
+                .config(config)
                 .includes(&self.include, false)
                 .includes(&self.layouts, true)
                 .articles(&[&self.articles, &PathBuf::from(current)])
-                .sass(&self.scss, &[&current.join(self.scss_load_paths.clone())])
+                .sass(&self.scss, &load_paths)
                 .run();
 
             if self.serve {
                 let dest = Path::new("").join(&self.dest);
                 if self.watch {
                     thread::spawn(move || {
-                        if let Err(e) = serve(&dest, "127.0.0.1:4000") {
+                        if let Err(e) = serve(&dest, "127.0.0.1:4000", &baseurl) {
                             error!("{:?}", e);
                         }
                         process::exit(1);
                     });
-                } else if let Err(e) = serve(&dest, "127.0.0.1:4000") {
+                } else if let Err(e) = serve(&dest, "127.0.0.1:4000", &baseurl) {
                     error!("{:?}", e);
                 }
 
@@ -252,14 +359,24 @@ impl BuildCommand {
                             Ok(event) => {
                                 info!("{:?}", event);
                                 info!("re-building");
-                                mole::Build::new(&self.dest, self.backtrace)
+                                let mut config = match mole::config::SiteConfig::load(current) {
+                                    Ok(config) => config,
+                                    Err(e) => {
+                                        error!("{}", e);
+                                        continue;
+                                    }
+                                };
+                                self.overrides(&mut config);
+                                let load_paths = self.load_paths(current, &config);
+                                let load_paths: Vec<&Path> =
+                                    load_paths.iter().map(PathBuf::as_path).collect();
+                                // failures are already logged, keep watching so they can be fixed
+                                let _ = mole::Build::new(&self.dest, self.backtrace)
+                                    .config(config)
                                     .includes(&self.include, false)
                                     .includes(&self.layouts, true)
                                     .articles(&[&self.articles, &PathBuf::from(current)])
-                                    .sass(
-                                        &self.scss,
-                                        &[&current.join(self.scss_load_paths.clone())],
-                                    )
+                                    .sass(&self.scss, &load_paths)
                                     .run();
                             }
                             Err(e) => error!("watch error: {:?}", e),
@@ -267,11 +384,71 @@ impl BuildCommand {
                     }
                 }
             }
+
+            if built.is_ok() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
         } else {
             error!(
                 "{:?} is not a directory so could not find any files to build from",
                 current
             );
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "check-links",
+    description = "checks the links in an already built site"
+)]
+pub struct CheckLinksCommand {
+    #[argh(positional, default = "String::from(\"./\")")]
+    /// path to the site, .mole.toml is read from here
+    current: String,
+
+    #[argh(option, default = "PathBuf::from(\"_output/\")")]
+    /// path from 'current' to the built site
+    dest: PathBuf,
+
+    #[argh(switch)]
+    /// broken links fail the check instead of being warnings
+    strict: bool,
+
+    #[argh(option)]
+    /// overrides `baseurl` in .mole.toml e.g. /docs
+    baseurl: Option<String>,
+}
+
+impl CheckLinksCommand {
+    pub fn run(self) -> ExitCode {
+        let current = Path::new(&self.current);
+        let dest = current.join(&self.dest);
+        if !dest.is_dir() {
+            error!("{:?} is not a directory, has the site been built?", dest);
+            return ExitCode::FAILURE;
+        }
+
+        let mut config = match mole::config::SiteConfig::load(current) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+        config.build.strict |= self.strict;
+        if let Some(baseurl) = self.baseurl {
+            config.baseurl = baseurl;
+        }
+
+        if mole::check_links(&dest, &config).is_ok() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
         }
     }
 }
@@ -0,0 +1,393 @@
+use chrono::FixedOffset;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::error::{CustomError, Span, WarningKind};
+
+/// name of the site configuration file, it lives in the root of the project
+/// and is also what identifies a folder as a mole project
+pub const CONFIG_FILE: &str = ".mole.toml";
+
+/// site wide configuration loaded from `.mole.toml`
+/// every field is optional so an empty (or missing) file gives the defaults
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SiteConfig {
+    pub title: String,
+    pub description: String,
+    pub url: String,
+    pub baseurl: String,
+    pub email: String,
+
+    /// `page.excerpt` is the content up to the first occurrence of this,
+    /// the default of a blank line means the first paragraph
+    pub excerpt_separator: String,
+
+    /// the offset dates are shown in and that dates without one are in e.g. `"+01:00"`
+    #[serde(deserialize_with = "deserialize_timezone")]
+    pub timezone: FixedOffset,
+    /// strftime format used by `{{ page.date | date }}` when it isn't given one
+    pub date_format: String,
+
+    pub markdown: MarkdownConfig,
+    pub highlight: HighlightConfig,
+    pub sass: SassConfig,
+    pub assets: AssetsConfig,
+    pub images: ImagesConfig,
+    pub build: BuildConfig,
+}
+
+/// `[build]` in `.mole.toml`
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct BuildConfig {
+    /// warnings fail the build, the same as `mole build --strict`
+    pub strict: bool,
+    /// warnings that are ignored e.g. `["jekyll_include", "broken_link"]`
+    pub allow: Vec<WarningKind>,
+    /// `{% include nav.html %}` includes the file `nav.html` like in jekyll, instead of
+    /// being the variable `nav.html`
+    pub jekyll_includes: bool,
+    /// checks the links in the output point at files that exist after each build, the same
+    /// as `mole build --check-links`
+    pub check_links: bool,
+    /// collapses whitespace and removes comments from the html, on unless it's turned off
+    /// here. It's always off with `--watch`
+    pub minify: bool,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        BuildConfig {
+            strict: false,
+            allow: Vec::new(),
+            jekyll_includes: false,
+            check_links: false,
+            minify: true,
+        }
+    }
+}
+
+/// the pulldown-cmark extensions, `[markdown]` in `.mole.toml`,
+/// articles can override these in their front matter e.g. `tables: false`
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+#[serde(default)]
+pub struct MarkdownConfig {
+    pub tables: bool,
+    pub footnotes: bool,
+    pub strikethrough: bool,
+    pub tasklists: bool,
+    /// turns "quotes" into “curly quotes”, liquid tags are left alone
+    pub smart_punctuation: bool,
+    /// gives headings a slug `id` so they can be linked to
+    pub heading_ids: bool,
+    /// adds a `<a class="anchor" href="#id">#</a>` permalink to each heading
+    pub heading_anchors: bool,
+    /// the smallest heading (h1 = 1) included in `page.toc`
+    pub toc_depth: u32,
+    /// render liquid before the markdown like jekyll does, by default the liquid is
+    /// protected from the markdown and rendered afterwards
+    pub liquid_first: bool,
+}
+
+impl Default for MarkdownConfig {
+    fn default() -> Self {
+        MarkdownConfig {
+            tables: true,
+            footnotes: true,
+            strikethrough: true,
+            tasklists: true,
+            smart_punctuation: true,
+            heading_ids: true,
+            heading_anchors: false,
+            toc_depth: 3,
+            liquid_first: false,
+        }
+    }
+}
+
+/// `[assets]` in `.mole.toml`
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct AssetsConfig {
+    /// puts a hash of the contents in the name of the css and copied files e.g.
+    /// `main.3f9a1c2b.css` so they can be cached forever, `asset_url` gives the new name
+    pub fingerprint: bool,
+    /// the kinds of file that are fingerprinted, anything else keeps it's name as do
+    /// `robots.txt`, `favicon.ico`, `CNAME` and the like
+    pub extensions: Vec<String>,
+    /// where the names are listed when fingerprinting, from the output directory
+    pub manifest: String,
+}
+
+impl Default for AssetsConfig {
+    fn default() -> Self {
+        AssetsConfig {
+            fingerprint: false,
+            extensions: [
+                "css", "js", "png", "jpg", "jpeg", "gif", "webp", "svg", "woff", "woff2",
+            ]
+            .iter()
+            .map(|e| e.to_string())
+            .collect(),
+            manifest: String::from("manifest.json"),
+        }
+    }
+}
+
+/// `[images]` in `.mole.toml`, for `{% image %}`
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ImagesConfig {
+    /// a resized copy of each image is made for every width that's smaller than it
+    pub widths: Vec<u32>,
+    /// the copies are webps instead of the image's own format, with a full size one as well
+    pub webp: bool,
+    /// the `sizes` of the `<img>` i.e. how wide the image is shown on the page
+    pub sizes: String,
+    /// resized images are kept here between builds, from the root of the site
+    pub cache: PathBuf,
+}
+
+impl Default for ImagesConfig {
+    fn default() -> Self {
+        ImagesConfig {
+            widths: Vec::new(),
+            webp: false,
+            sizes: String::from("100vw"),
+            cache: PathBuf::from(".mole-cache"),
+        }
+    }
+}
+
+/// `[sass]` in `.mole.toml`
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SassConfig {
+    pub style: SassStyle,
+    /// where `@import`s are looked for after the file's own directory, from the root of the
+    /// site e.g. `["_sass", "node_modules/bootstrap/scss"]`
+    pub load_paths: Vec<PathBuf>,
+    /// `[sass.variables]` are defined at the start of every stylesheet e.g.
+    /// `brand = "#ff6600"` is `$brand: #ff6600;`
+    pub variables: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SassStyle {
+    /// each rule and declaration on it's own line
+    #[default]
+    Expanded,
+    /// the whole stylesheet on one line
+    Compressed,
+}
+
+/// syntax highlighting for fenced code blocks, `[highlight]` in `.mole.toml`
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct HighlightConfig {
+    pub enabled: bool,
+    /// one of the themes bundled with syntect e.g. "InspiredGitHub", "base16-ocean.dark"
+    pub theme: String,
+    /// use css classes and write the theme out to `stylesheet` instead of using inline styles
+    pub css_classes: bool,
+    pub stylesheet: String,
+    pub line_numbers: bool,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        HighlightConfig {
+            enabled: true,
+            theme: String::from("InspiredGitHub"),
+            css_classes: false,
+            stylesheet: String::from("highlight.css"),
+            line_numbers: false,
+        }
+    }
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        SiteConfig {
+            title: String::from(""),
+            description: String::from(""),
+            url: String::from(""),
+            baseurl: String::from(""),
+            email: String::from(""),
+            excerpt_separator: String::from("\n\n"),
+            timezone: FixedOffset::east(0),
+            date_format: String::from("%b %-d, %Y"),
+            markdown: MarkdownConfig::default(),
+            highlight: HighlightConfig::default(),
+            sass: SassConfig::default(),
+            assets: AssetsConfig::default(),
+            images: ImagesConfig::default(),
+            build: BuildConfig::default(),
+        }
+    }
+}
+
+fn deserialize_timezone<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<FixedOffset, D::Error> {
+    let timezone = String::deserialize(deserializer)?;
+    crate::parse::parse_offset(&timezone).ok_or_else(|| {
+        serde::de::Error::custom(format!(
+            "unknown timezone '{}' expected an offset like \"+01:00\" or \"UTC\"",
+            timezone
+        ))
+    })
+}
+
+impl SiteConfig {
+    /// looks for `.mole.toml` in `dir`, if there isn't one then the defaults are used
+    pub fn load(dir: &Path) -> Result<Self, CustomError> {
+        let path = dir.join(CONFIG_FILE);
+        if path.is_file() {
+            let content = crate::util::read_file(&path)?;
+            toml::from_str(&content).map_err(|e| {
+                let mut error = CustomError::config(e.to_string()).path(&path);
+                // toml gives a zero based line and column
+                if let Some((line, col)) = e.line_col() {
+                    error = error.span(Span {
+                        line: line + 1,
+                        start: col,
+                        end: col + 1,
+                        text: content.lines().nth(line).unwrap_or("").to_string(),
+                    });
+                }
+                error
+            })
+        } else {
+            Ok(SiteConfig::default())
+        }
+    }
+
+    /// the values that are exposed to templates as `site`
+    pub fn to_liquid(&self) -> liquid::Object {
+        liquid::object!({
+            "title": self.title,
+            "description": self.description,
+            "url": self.url,
+            "baseurl": self.baseurl,
+            "email": self.email,
+        })
+    }
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn empty_config() {
+        assert_eq!(SiteConfig::default(), toml::from_str("").unwrap());
+    }
+
+    #[test]
+    fn partial_config() {
+        let config: SiteConfig =
+            toml::from_str("title = \"mole\"\nexcerpt_separator = \"<!--more-->\"").unwrap();
+        assert_eq!("mole", config.title);
+        assert_eq!("<!--more-->", config.excerpt_separator);
+        assert_eq!("", config.baseurl);
+    }
+
+    #[test]
+    fn markdown_config() {
+        let config: SiteConfig =
+            toml::from_str("[markdown]\ntables = false\nsmart_punctuation = false").unwrap();
+        assert!(!config.markdown.tables);
+        assert!(config.markdown.footnotes);
+        assert!(!config.markdown.smart_punctuation);
+        assert!(SiteConfig::default().markdown.smart_punctuation);
+    }
+
+    #[test]
+    fn highlight_config() {
+        let config: SiteConfig =
+            toml::from_str("[highlight]\ntheme = \"base16-ocean.dark\"\nline_numbers = true")
+                .unwrap();
+        assert_eq!("base16-ocean.dark", config.highlight.theme);
+        assert!(config.highlight.line_numbers);
+        assert!(config.highlight.enabled);
+    }
+
+    #[test]
+    fn dates_config() {
+        let config: SiteConfig =
+            toml::from_str("timezone = \"-05:00\"\ndate_format = \"%Y\"").unwrap();
+        assert_eq!(FixedOffset::west(5 * 3600), config.timezone);
+        assert_eq!("%Y", config.date_format);
+        assert_eq!(FixedOffset::east(0), SiteConfig::default().timezone);
+        assert!(toml::from_str::<SiteConfig>("timezone = \"Europe/London\"").is_err());
+    }
+
+    #[test]
+    fn build_config() {
+        let config: SiteConfig =
+            toml::from_str("[build]\nstrict = true\nallow = [\"jekyll_include\", \"broken_link\"]")
+                .unwrap();
+        assert!(config.build.strict);
+        assert!(!config.build.jekyll_includes);
+        assert!(!config.build.check_links);
+        assert!(config.build.minify);
+        assert_eq!(
+            vec![WarningKind::JekyllInclude, WarningKind::BrokenLink],
+            config.build.allow
+        );
+        assert!(toml::from_str::<SiteConfig>("[build]\nallow = [\"nope\"]").is_err());
+    }
+
+    #[test]
+    fn sass_config() {
+        let config: SiteConfig =
+            toml::from_str("[sass]\nstyle = \"compressed\"\nload_paths = [\"_sass\"]\n[sass.variables]\nbrand = \"#ff6600\"").unwrap();
+        assert_eq!(SassStyle::Compressed, config.sass.style);
+        assert_eq!(vec![PathBuf::from("_sass")], config.sass.load_paths);
+        assert_eq!(
+            Some("#ff6600"),
+            config.sass.variables.get("brand").map(String::as_str)
+        );
+        assert_eq!(SassStyle::Expanded, SiteConfig::default().sass.style);
+        assert!(toml::from_str::<SiteConfig>("[sass]\nstyle = \"nested\"").is_err());
+    }
+
+    #[test]
+    fn assets_config() {
+        let config: SiteConfig = toml::from_str("[assets]\nfingerprint = true").unwrap();
+        assert!(config.assets.fingerprint);
+        assert_eq!("manifest.json", config.assets.manifest);
+        assert!(config.assets.extensions.contains(&String::from("css")));
+        let config: SiteConfig = toml::from_str("[assets]\nextensions = [\"css\"]").unwrap();
+        assert_eq!(vec![String::from("css")], config.assets.extensions);
+    }
+
+    #[test]
+    fn images_config() {
+        let config: SiteConfig = toml::from_str("[images]\nwidths = [480, 960]").unwrap();
+        assert_eq!(vec![480, 960], config.images.widths);
+        assert!(!config.images.webp);
+        let config: SiteConfig = toml::from_str("[images]\nwebp = true").unwrap();
+        assert!(config.images.webp);
+        assert_eq!("100vw", config.images.sizes);
+        assert_eq!(PathBuf::from(".mole-cache"), config.images.cache);
+    }
+
+    #[test]
+    fn invalid_config() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(CONFIG_FILE),
+            "title = \"mole\"\ntables = \n",
+        )
+        .unwrap();
+        let e = SiteConfig::load(dir.path()).unwrap_err();
+        assert_eq!(Some(dir.path().join(CONFIG_FILE)), e.path);
+        assert_eq!(2, e.span.unwrap().line);
+    }
+}
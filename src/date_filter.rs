@@ -0,0 +1,440 @@
+use chrono::{Datelike, FixedOffset, NaiveDate, NaiveDateTime, TimeZone};
+use liquid::ValueView;
+use liquid_core::parser::FilterArguments;
+use liquid_core::{
+    Display_filter, Error, Expression, Filter, FilterParameters, FilterReflection, ParseFilter,
+    Result, Runtime, Value,
+};
+use std::fmt::Write;
+
+use crate::config::SiteConfig;
+use crate::util::invalid_argument;
+
+type Date = chrono::DateTime<FixedOffset>;
+
+/// chrono's date as one liquid knows about so `date` etc. work on it
+pub fn liquid_date(date: Date) -> liquid::model::scalar::DateTime {
+    let mut liquid_date = liquid::model::scalar::DateTime::default();
+    *liquid_date = date;
+    liquid_date
+}
+
+/// liquid dates are moved into the site's timezone, strings can be liquid's format
+/// `2020-01-03 10:00:00 +0000` or the front matter's `2020-01-03` and `2020-01-03 10:00`
+fn as_date(input: &dyn ValueView, timezone: &FixedOffset) -> Option<Date> {
+    let scalar = input.as_scalar()?;
+    if let Some(date) = scalar.to_date_time() {
+        return Some(*date.with_timezone(timezone));
+    }
+    let input = scalar.to_kstr();
+    let naive = NaiveDate::parse_from_str(&input, "%Y-%m-%d")
+        .map(|date| date.and_hms(0, 0, 0))
+        .or_else(|_| NaiveDateTime::parse_from_str(&input, "%Y-%m-%d %H:%M:%S"))
+        .or_else(|_| NaiveDateTime::parse_from_str(&input, "%Y-%m-%d %H:%M"))
+        .ok()?;
+    timezone.from_local_datetime(&naive).single()
+}
+
+/// a bad strftime format is an error instead of a panic
+fn format(date: &Date, format: &str) -> Result<String> {
+    let mut output = String::new();
+    write!(output, "{}", date.format(format))
+        .map_err(|_| Error::with_msg(format!("invalid date format '{}'", format)))?;
+    Ok(output)
+}
+
+fn ordinal(day: u32) -> &'static str {
+    match (day % 10, day % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    }
+}
+
+/// jekyll's `date_to_string` and `date_to_long_string`, `ordinal` gives `7th Nov 2008` and
+/// with `US` as well `Nov 7th, 2008`
+fn date_to_string(date: &Date, month: &str, ordinal_day: bool, us: bool) -> Result<String> {
+    if !ordinal_day {
+        return format(date, &format!("%d {} %Y", month));
+    }
+    let day = format!("{}{}", date.day(), ordinal(date.day()));
+    if us {
+        Ok(format!(
+            "{} {}, {}",
+            format(date, month)?,
+            day,
+            date.format("%Y")
+        ))
+    } else {
+        Ok(format!(
+            "{} {} {}",
+            day,
+            format(date, month)?,
+            date.format("%Y")
+        ))
+    }
+}
+
+#[derive(Debug, FilterParameters)]
+struct DateArgs {
+    #[parameter(
+        description = "The strftime format, the site's `date_format` if not given",
+        arg_type = "str"
+    )]
+    format: Option<Expression>,
+}
+
+#[derive(Clone, FilterReflection)]
+#[filter(
+    name = "date",
+    description = "Formats a date in the site's timezone",
+    parameters(DateArgs),
+    parsed(DateFilter)
+)]
+pub struct DateFormat {
+    timezone: FixedOffset,
+    format: String,
+}
+
+impl DateFormat {
+    pub fn new(config: &SiteConfig) -> Self {
+        DateFormat {
+            timezone: config.timezone,
+            format: config.date_format.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Display_filter)]
+#[name = "date"]
+struct DateFilter {
+    #[parameters]
+    args: DateArgs,
+    timezone: FixedOffset,
+    format: String,
+}
+
+impl ParseFilter for DateFormat {
+    fn parse(&self, arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        Ok(Box::new(DateFilter {
+            args: DateArgs::from_args(arguments)?,
+            timezone: self.timezone,
+            format: self.format.clone(),
+        }))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self as &dyn FilterReflection
+    }
+}
+
+impl Filter for DateFilter {
+    fn evaluate(&self, input: &dyn ValueView, runtime: &Runtime) -> Result<Value> {
+        let args = self.args.evaluate(runtime)?;
+        match as_date(input, &self.timezone) {
+            Some(date) => Ok(Value::scalar(format(
+                &date,
+                args.format.as_deref().unwrap_or(&self.format),
+            )?)),
+            None => Ok(input.to_value()),
+        }
+    }
+}
+
+#[derive(Debug, FilterParameters)]
+struct DateToStringArgs {
+    #[parameter(description = "`ordinal` for 7th instead of 07", arg_type = "str")]
+    kind: Option<Expression>,
+    #[parameter(description = "`US` to put the month first", arg_type = "str")]
+    style: Option<Expression>,
+}
+
+#[derive(Clone, FilterReflection)]
+#[filter(
+    name = "date_to_string",
+    description = "A date like `07 Nov 2008`",
+    parameters(DateToStringArgs),
+    parsed(DateToStringFilter)
+)]
+pub struct DateToString {
+    timezone: FixedOffset,
+}
+
+impl DateToString {
+    pub fn new(config: &SiteConfig) -> Self {
+        DateToString {
+            timezone: config.timezone,
+        }
+    }
+}
+
+/// `date_to_string` and `date_to_long_string`
+#[derive(Debug)]
+struct DateToStringFilter {
+    name: &'static str,
+    args: DateToStringArgs,
+    timezone: FixedOffset,
+    /// `%b` or `%B` for the long version
+    month: &'static str,
+}
+
+impl std::fmt::Display for DateToStringFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} : {}", self.name, self.args)
+    }
+}
+
+impl ParseFilter for DateToString {
+    fn parse(&self, arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        Ok(Box::new(DateToStringFilter {
+            name: "date_to_string",
+            args: DateToStringArgs::from_args(arguments)?,
+            timezone: self.timezone,
+            month: "%b",
+        }))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self as &dyn FilterReflection
+    }
+}
+
+impl Filter for DateToStringFilter {
+    fn evaluate(&self, input: &dyn ValueView, runtime: &Runtime) -> Result<Value> {
+        let args = self.args.evaluate(runtime)?;
+        let ordinal_day = match args.kind.as_deref() {
+            None => false,
+            Some("ordinal") => true,
+            Some(_) => return Err(invalid_argument("kind", "expected `ordinal`")),
+        };
+        let us = match args.style.as_deref() {
+            None | Some("UK") => false,
+            Some("US") => true,
+            Some(_) => return Err(invalid_argument("style", "expected `US` or `UK`")),
+        };
+        match as_date(input, &self.timezone) {
+            Some(date) => Ok(Value::scalar(date_to_string(
+                &date,
+                self.month,
+                ordinal_day,
+                us,
+            )?)),
+            None => Ok(input.to_value()),
+        }
+    }
+}
+
+#[derive(Clone, FilterReflection)]
+#[filter(
+    name = "date_to_long_string",
+    description = "A date like `07 November 2008`",
+    parameters(DateToStringArgs),
+    parsed(DateToStringFilter)
+)]
+pub struct DateToLongString {
+    timezone: FixedOffset,
+}
+
+impl DateToLongString {
+    pub fn new(config: &SiteConfig) -> Self {
+        DateToLongString {
+            timezone: config.timezone,
+        }
+    }
+}
+
+impl ParseFilter for DateToLongString {
+    fn parse(&self, arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        Ok(Box::new(DateToStringFilter {
+            name: "date_to_long_string",
+            args: DateToStringArgs::from_args(arguments)?,
+            timezone: self.timezone,
+            month: "%B",
+        }))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self as &dyn FilterReflection
+    }
+}
+
+/// `date_to_xmlschema` and `date_to_rfc822`
+#[derive(Debug)]
+struct FixedFormatFilter {
+    name: &'static str,
+    timezone: FixedOffset,
+    format: &'static str,
+}
+
+impl std::fmt::Display for FixedFormatFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl Filter for FixedFormatFilter {
+    fn evaluate(&self, input: &dyn ValueView, _runtime: &Runtime) -> Result<Value> {
+        match as_date(input, &self.timezone) {
+            Some(date) => Ok(Value::scalar(format(&date, self.format)?)),
+            None => Ok(input.to_value()),
+        }
+    }
+}
+
+#[derive(Clone, FilterReflection)]
+#[filter(
+    name = "date_to_xmlschema",
+    description = "A date like `2008-11-07T13:07:54-08:00` for feeds and sitemaps",
+    parsed(FixedFormatFilter)
+)]
+pub struct DateToXmlschema {
+    timezone: FixedOffset,
+}
+
+impl DateToXmlschema {
+    pub fn new(config: &SiteConfig) -> Self {
+        DateToXmlschema {
+            timezone: config.timezone,
+        }
+    }
+}
+
+impl ParseFilter for DateToXmlschema {
+    fn parse(&self, _arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        Ok(Box::new(FixedFormatFilter {
+            name: "date_to_xmlschema",
+            timezone: self.timezone,
+            format: "%Y-%m-%dT%H:%M:%S%:z",
+        }))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self as &dyn FilterReflection
+    }
+}
+
+#[derive(Clone, FilterReflection)]
+#[filter(
+    name = "date_to_rfc822",
+    description = "A date like `Fri, 07 Nov 2008 13:07:54 -0800` for rss",
+    parsed(FixedFormatFilter)
+)]
+pub struct DateToRfc822 {
+    timezone: FixedOffset,
+}
+
+impl DateToRfc822 {
+    pub fn new(config: &SiteConfig) -> Self {
+        DateToRfc822 {
+            timezone: config.timezone,
+        }
+    }
+}
+
+impl ParseFilter for DateToRfc822 {
+    fn parse(&self, _arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        Ok(Box::new(FixedFormatFilter {
+            name: "date_to_rfc822",
+            timezone: self.timezone,
+            format: "%a, %d %b %Y %H:%M:%S %z",
+        }))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self as &dyn FilterReflection
+    }
+}
+
+#[cfg(test)]
+mod date_filter_tests {
+    use super::*;
+    use crate::util::render_with;
+    use pretty_assertions::assert_eq;
+
+    fn render(template: &str, config: &SiteConfig) -> String {
+        let date = FixedOffset::east(0)
+            .from_local_datetime(&NaiveDate::from_ymd(2008, 11, 7).and_hms(21, 7, 54))
+            .unwrap();
+        let filters = |builder: liquid::ParserBuilder| {
+            builder
+                .filter(DateFormat::new(config))
+                .filter(DateToString::new(config))
+                .filter(DateToLongString::new(config))
+                .filter(DateToXmlschema::new(config))
+                .filter(DateToRfc822::new(config))
+        };
+        render_with(
+            filters,
+            template,
+            &liquid::object!({
+                "page": { "date": Value::scalar(liquid_date(date)), "day": "2020-01-03" }
+            }),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn formats() {
+        let config = SiteConfig::default();
+        assert_eq!(
+            "2008-11-07T21:07:54+00:00",
+            render("{{ page.date | date_to_xmlschema }}", &config)
+        );
+        assert_eq!(
+            "Fri, 07 Nov 2008 21:07:54 +0000",
+            render("{{ page.date | date_to_rfc822 }}", &config)
+        );
+        assert_eq!(
+            "07 Nov 2008 07 November 2008",
+            render(
+                "{{ page.date | date_to_string }} {{ page.date | date_to_long_string }}",
+                &config
+            )
+        );
+        assert_eq!(
+            "7th Nov 2008 November 7th, 2008",
+            render(
+                "{{ page.date | date_to_string: 'ordinal' }} \
+                 {{ page.date | date_to_long_string: 'ordinal', 'US' }}",
+                &config
+            )
+        );
+        assert_eq!("2020", render("{{ page.day | date: '%Y' }}", &config));
+    }
+
+    #[test]
+    fn site_format_and_timezone() {
+        let config = SiteConfig {
+            timezone: FixedOffset::east(3 * 3600),
+            date_format: String::from("%-d %B %H:%M"),
+            ..SiteConfig::default()
+        };
+        assert_eq!(
+            "8 November 00:07",
+            render("{{ page.date | date }}", &config)
+        );
+        assert_eq!(
+            "2008-11-08T00:07:54+03:00",
+            render("{{ page.date | date_to_xmlschema }}", &config)
+        );
+        // dates without an offset are already in the site's timezone
+        assert_eq!(
+            "2020-01-03T00:00:00+03:00",
+            render("{{ page.day | date_to_xmlschema }}", &config)
+        );
+    }
+
+    #[test]
+    fn ordinals() {
+        let days: Vec<&str> = [1, 2, 3, 4, 11, 12, 13, 21, 22, 23, 31]
+            .iter()
+            .map(|day| ordinal(*day))
+            .collect();
+        assert_eq!(
+            vec!["st", "nd", "rd", "th", "th", "th", "th", "st", "nd", "rd", "st"],
+            days
+        );
+    }
+}
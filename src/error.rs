@@ -1,70 +1,290 @@
-use std::{fmt::Display, path::PathBuf};
+use log::{debug, error, warn};
+use serde::Deserialize;
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Warning(WarningKind),
+    Error,
+}
+
+/// the kinds of warnings, any of these can be allowed in `.mole.toml` so that
+/// they don't fail a `--strict` build e.g. `[build] allow = ["jekyll_include"]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// `layout` and `base_layout` are the same which can overflow the stack
+    LayoutLoop,
+    /// `{% include foo %}` where `foo` is a variable, in jekyll that would be the file foo
+    JekyllInclude,
+    /// a layout and an include (or two includes) have the same name
+    DuplicateInclude,
+    /// a link in the output to a file or `#anchor` that doesn't exist
+    BrokenLink,
+    /// the excerpt failed to render on it's own so `page.excerpt` is missing
+    Excerpt,
+    /// an image couldn't be resized so only the original is used
+    Image,
+}
+
+impl Display for WarningKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            WarningKind::LayoutLoop => "layout_loop",
+            WarningKind::JekyllInclude => "jekyll_include",
+            WarningKind::DuplicateInclude => "duplicate_include",
+            WarningKind::BrokenLink => "broken_link",
+            WarningKind::Excerpt => "excerpt",
+            WarningKind::Image => "image",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    IO,
+    Liquid,
+    Config,
+    Sass,
+    InvalidKey,
+    EmptyValue,
+    InvalidValue,
+    InvalidConfig,
+    BrokenLink,
+    Image,
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ErrorKind::IO => "IO error",
+            ErrorKind::Liquid => "Liquid error",
+            ErrorKind::Config => "Config error",
+            ErrorKind::Sass => "Sass error",
+            ErrorKind::InvalidKey => "Invalid key",
+            ErrorKind::EmptyValue => "Empty value",
+            ErrorKind::InvalidValue => "Invalid value",
+            ErrorKind::InvalidConfig => "Invalid configuration",
+            ErrorKind::BrokenLink => "Broken link",
+            ErrorKind::Image => "Image error",
+        })
+    }
+}
+
+/// the line an error happened on, `start` and `end` are the columns to underline
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// every error in mole, the path and span are filled in when we know them so
+/// the error can point at the exact file and line that caused it
 #[derive(Debug, PartialEq)]
-pub enum CustomError {
-    IOError(String),
-    LiquidError(String),
+pub struct CustomError {
+    pub kind: ErrorKind,
+    pub severity: Severity,
+    pub message: String,
+    pub path: Option<PathBuf>,
+    /// boxed to keep the error small as it's returned everywhere
+    pub span: Option<Box<Span>>,
+    pub cause: Option<String>,
+    /// e.g. `did you mean "title"?`
+    pub help: Option<String>,
+}
+
+impl CustomError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        CustomError {
+            kind,
+            severity: Severity::Error,
+            message: message.into(),
+            path: None,
+            span: None,
+            cause: None,
+            help: None,
+        }
+    }
+
+    pub fn io(message: impl Into<String>) -> Self {
+        CustomError::new(ErrorKind::IO, message)
+    }
+
+    pub fn liquid(message: impl Into<String>) -> Self {
+        CustomError::new(ErrorKind::Liquid, message)
+    }
+
+    pub fn config(message: impl Into<String>) -> Self {
+        CustomError::new(ErrorKind::Config, message)
+    }
+
+    pub fn path(mut self, path: &Path) -> Self {
+        self.path = Some(path.to_path_buf());
+        self
+    }
+
+    pub fn span(mut self, span: Span) -> Self {
+        self.span = Some(Box::new(span));
+        self
+    }
+
+    pub fn cause(mut self, cause: impl Display) -> Self {
+        self.cause = Some(cause.to_string());
+        self
+    }
+
+    pub fn help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn warning(mut self, kind: WarningKind) -> Self {
+        self.severity = Severity::Warning(kind);
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// logs the error at the level that matches it's severity
+    pub fn log(&self) {
+        match self.severity {
+            Severity::Error => error!("{}", self),
+            Severity::Warning(_) => warn!("{}", self),
+        }
+    }
+
+    /// warnings show their kind so it's clear what to put in the allow list
+    fn category(&self) -> String {
+        match self.severity {
+            Severity::Warning(kind) => format!(" [{}]", kind),
+            Severity::Error => String::new(),
+        }
+    }
+
+    /// a single line e.g. `_articles/foo.md:3:5 Invalid value: empty value` used for the build summary
+    pub fn summary(&self) -> String {
+        let location = match (&self.path, &self.span) {
+            (Some(path), Some(span)) => format!("{}:{}:{} ", path.display(), span.line, span.start),
+            (Some(path), None) => format!("{} ", path.display()),
+            _ => String::new(),
+        };
+        let message = self.message.lines().next().unwrap_or("");
+        format!("{}{}{}: {}", location, self.kind, self.category(), message)
+    }
 }
 
 impl Display for CustomError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            CustomError::IOError(s) => writeln!(f, "IO error: {}", s),
-            CustomError::LiquidError(s) => writeln!(f, "Liquid error: {}", s),
+        // liquid errors are multi-line so the location goes under the first line
+        let (first, rest) = match self.message.split_once('\n') {
+            Some((first, rest)) => (first, rest.trim_end()),
+            None => (self.message.as_str(), ""),
+        };
+        writeln!(f, "{}{}: {}", self.kind, self.category(), first)?;
+
+        match (&self.path, &self.span) {
+            (Some(path), Some(span)) => {
+                let spacing = " ".repeat(span.line.to_string().len() + 1);
+                let underline = format!(
+                    "{}{}",
+                    " ".repeat(span.start),
+                    "^".repeat(span.end.saturating_sub(span.start).max(1))
+                );
+                writeln!(
+                    f,
+                    "{}--> {}:{}:{}",
+                    spacing,
+                    path.display(),
+                    span.line,
+                    span.start
+                )?;
+                writeln!(f, "{} |", spacing)?;
+                writeln!(f, "{:w$} | {}", span.line, span.text, w = spacing.len())?;
+                writeln!(f, "{} | {}", spacing, underline)?;
+                writeln!(f, "{} |", spacing)?;
+            }
+            (Some(path), None) => writeln!(f, "  --> {}", path.display())?,
+            _ => {}
+        }
+
+        if !rest.is_empty() {
+            writeln!(f, "{}", rest)?;
         }
+        if let Some(help) = &self.help {
+            writeln!(f, "help: {}", help)?;
+        }
+        if let Some(cause) = &self.cause {
+            writeln!(f, "caused by: {}", cause)?;
+        }
+        Ok(())
     }
 }
 
-impl From<std::io::Error> for CustomError {
-    fn from(e: std::io::Error) -> Self {
-        CustomError::IOError(e.to_string())
-    }
+/// warnings collected from all over the build (including inside liquid tags) so they can be
+/// reported at the end and fail a `--strict` build, it's shared so cloning is cheap
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    inner: Arc<Mutex<DiagnosticsInner>>,
 }
 
-impl From<liquid::Error> for CustomError {
-    fn from(e: liquid::Error) -> Self {
-        CustomError::LiquidError(e.to_string())
-    }
+#[derive(Debug, Default)]
+struct DiagnosticsInner {
+    allow: Vec<WarningKind>,
+    warnings: Vec<CustomError>,
 }
 
-pub fn parse_error_message(
-    message: &str,
-    path: &PathBuf,
-    line: &str,
-    start: usize,
-    end: usize,
-    lineno: i8,
-) -> String {
-    let spacing = if lineno < 99 {
-        "  "
-    } else if lineno < 127 {
-        "   "
-    } else {
-        "    "
-    };
+impl Diagnostics {
+    /// warnings of these kinds are ignored
+    pub fn allow(&self, kinds: &[WarningKind]) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.allow = kinds.to_vec();
+        }
+    }
 
-    let mut underline = String::new();
-    for _i in 0..start {
-        underline.push(' ');
+    /// logs the warning the first time it's seen, templates are parsed more than once
+    /// so the same warning can be found multiple times
+    pub fn warn(&self, e: CustomError) {
+        if let Ok(mut inner) = self.inner.lock() {
+            if let Severity::Warning(kind) = e.severity {
+                if inner.allow.contains(&kind) {
+                    debug!("allowed {}", e);
+                    return;
+                }
+            }
+
+            if !inner.warnings.contains(&e) {
+                e.log();
+                inner.warnings.push(e);
+            }
+        }
     }
 
-    for _i in start..end {
-        underline.push('^');
+    pub fn take(&self) -> Vec<CustomError> {
+        match self.inner.lock() {
+            Ok(mut inner) => std::mem::take(&mut inner.warnings),
+            Err(_) => Vec::new(),
+        }
     }
+}
 
-    let msg = format!(
-        "\n{s   } --> {p} {n}:{start}\n{s   } |\n{n:w$} | {line}\n{s   } | {underline}\n{s   } |\n{s  }{m}",
-        p = path.to_str().unwrap(),
-        line = line,
-        s = spacing,
-        w = spacing.len(),
-        underline = underline,
-        n = lineno,
-        start = start,
-        m = message
-);
+impl From<std::io::Error> for CustomError {
+    fn from(e: std::io::Error) -> Self {
+        CustomError::io(e.to_string())
+    }
+}
 
-    msg
+impl From<liquid::Error> for CustomError {
+    fn from(e: liquid::Error) -> Self {
+        CustomError::liquid(e.to_string())
+    }
 }
 
 /*
@@ -145,3 +365,84 @@ fn render(&self) -> {
 */
 
 // mole --build --backtrace
+
+#[cfg(test)]
+mod error_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn display_span() {
+        let e = CustomError::new(ErrorKind::InvalidValue, "empty value")
+            .path(Path::new("test.md"))
+            .span(Span {
+                line: 3,
+                start: 6,
+                end: 8,
+                text: "title:".into(),
+            });
+        assert_eq!(
+            "Invalid value: empty value\n  --> test.md:3:6\n   |\n 3 | title:\n   |       ^^\n   |\n",
+            e.to_string()
+        );
+        assert_eq!("test.md:3:6 Invalid value: empty value", e.summary());
+    }
+
+    #[test]
+    fn display_cause() {
+        let e = CustomError::io("could not write file")
+            .path(Path::new("_output/a.html"))
+            .cause("permission denied");
+        assert_eq!(
+            "IO error: could not write file\n  --> _output/a.html\ncaused by: permission denied\n",
+            e.to_string()
+        );
+        assert!(e.is_error());
+        assert!(!e.warning(WarningKind::Image).is_error());
+    }
+
+    #[test]
+    fn display_help() {
+        let e = CustomError::liquid(
+            "liquid: Unknown variable
+  with:
+    requested variable=paeg",
+        )
+        .help("did you mean `page`?");
+        assert_eq!(
+            "Liquid error: liquid: Unknown variable
+  with:
+    requested variable=paeg
+help: did you mean `page`?
+",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn diagnostics() {
+        let diagnostics = Diagnostics::default();
+        diagnostics.allow(&[WarningKind::Image]);
+        let shared = diagnostics.clone();
+        for _ in 0..2 {
+            shared
+                .warn(CustomError::new(ErrorKind::Liquid, "a").warning(WarningKind::JekyllInclude));
+        }
+        shared.warn(CustomError::new(ErrorKind::Image, "b").warning(WarningKind::Image));
+
+        let warnings = diagnostics.take();
+        assert_eq!(1, warnings.len());
+        assert_eq!("Liquid error [jekyll_include]: a", warnings[0].summary());
+        assert!(diagnostics.take().is_empty());
+    }
+
+    #[test]
+    fn display_multi_line() {
+        let e = CustomError::liquid("liquid: Unknown filter\n  with:\n    filter=nope\n")
+            .path(Path::new("a.md"));
+        assert_eq!(
+            "Liquid error: liquid: Unknown filter\n  --> a.md\n  with:\n    filter=nope\n",
+            e.to_string()
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use log::warn;
+use pulldown_cmark::escape::escape_html;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Color, Theme, ThemeSet};
+use syntect::html::{
+    append_highlighted_html_for_styled_line, css_for_theme_with_class_style,
+    line_tokens_to_classed_spans, ClassStyle, IncludeBackground,
+};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+use crate::config::HighlightConfig;
+use crate::error::CustomError;
+
+/// prefixed so that the generated classes don't clash with the site's own css e.g. `.string`
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+const LINE_NUMBER_STYLE: &str = "user-select:none;opacity:0.5;padding-right:1em;";
+
+/// `<code class="language-rust">` with the language escaped like any other attribute
+fn code_tag(lang: &str) -> String {
+    let mut html = String::from("<code class=\"language-");
+    // writing to a string can't fail
+    escape_html(&mut html, lang).unwrap();
+    html.push_str("\">");
+    html
+}
+
+/// build time syntax highlighting for fenced code blocks, uses the syntaxes and themes
+/// bundled with syntect so it works offline
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+    css_classes: bool,
+    line_numbers: bool,
+}
+
+impl Highlighter {
+    pub fn new(config: &HighlightConfig) -> Result<Self, CustomError> {
+        let mut themes = ThemeSet::load_defaults();
+        let theme = match themes.themes.remove(&config.theme) {
+            Some(theme) => theme,
+            None => {
+                return Err(CustomError::config(format!(
+                    "unknown highlight theme {:?}, available themes: {}",
+                    config.theme,
+                    themes
+                        .themes
+                        .keys()
+                        .cloned()
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )))
+            }
+        };
+
+        Ok(Highlighter {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme,
+            css_classes: config.css_classes,
+            line_numbers: config.line_numbers,
+        })
+    }
+
+    /// returns the `<pre><code>` block for the code, or None if the language isn't known
+    /// in which case the code block should be left as it is
+    pub fn highlight(&self, code: &str, lang: &str) -> Option<String> {
+        let syntax = self.syntaxes.find_syntax_by_token(lang)?;
+        let html = if self.css_classes {
+            self.classed(code, syntax, lang)
+        } else {
+            self.inline(code, syntax, lang)
+        };
+
+        match html {
+            Ok(html) => Some(html),
+            Err(e) => {
+                warn!("failed to highlight {} code block {}", lang, e);
+                None
+            }
+        }
+    }
+
+    /// the theme as css, only needed when using css classes
+    pub fn stylesheet(&self) -> Option<String> {
+        if !self.css_classes {
+            return None;
+        }
+
+        match css_for_theme_with_class_style(&self.theme, CLASS_STYLE) {
+            Ok(css) => Some(format!("{}\n.hl-lineno {{ {} }}\n", css, LINE_NUMBER_STYLE)),
+            Err(e) => {
+                warn!("failed to generate highlight stylesheet {}", e);
+                None
+            }
+        }
+    }
+
+    fn inline(
+        &self,
+        code: &str,
+        syntax: &SyntaxReference,
+        lang: &str,
+    ) -> Result<String, syntect::Error> {
+        let background = self.theme.settings.background.unwrap_or(Color::WHITE);
+        let mut html = format!(
+            "<pre style=\"background-color:#{:02x}{:02x}{:02x};\">{}",
+            background.r,
+            background.g,
+            background.b,
+            code_tag(lang)
+        );
+
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+        for (i, line) in LinesWithEndings::from(code).enumerate() {
+            self.line_number(&mut html, i + 1);
+            let regions = highlighter.highlight_line(line, &self.syntaxes)?;
+            append_highlighted_html_for_styled_line(
+                &regions,
+                IncludeBackground::IfDifferent(background),
+                &mut html,
+            )?;
+        }
+
+        html.push_str("</code></pre>\n");
+        Ok(html)
+    }
+
+    fn classed(
+        &self,
+        code: &str,
+        syntax: &SyntaxReference,
+        lang: &str,
+    ) -> Result<String, syntect::Error> {
+        let mut html = format!("<pre class=\"hl-code\">{}", code_tag(lang));
+
+        let mut state = ParseState::new(syntax);
+        let mut stack = ScopeStack::new();
+        // spans can be left open at the end of a line e.g. multi-line comments
+        let mut open_spans = 0;
+        for (i, line) in LinesWithEndings::from(code).enumerate() {
+            self.line_number(&mut html, i + 1);
+            let ops = state.parse_line(line, &self.syntaxes)?;
+            let (spans, delta) = line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, &mut stack)?;
+            html.push_str(&spans);
+            open_spans += delta;
+        }
+
+        for _ in 0..open_spans {
+            html.push_str("</span>");
+        }
+        html.push_str("</code></pre>\n");
+        Ok(html)
+    }
+
+    fn line_number(&self, html: &mut String, n: usize) {
+        if self.line_numbers {
+            if self.css_classes {
+                html.push_str(&format!("<span class=\"hl-lineno\">{}</span>", n));
+            } else {
+                html.push_str(&format!(
+                    "<span style=\"{}\">{}</span>",
+                    LINE_NUMBER_STYLE, n
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod highlight_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn escaped_language() {
+        assert_eq!("<code class=\"language-rust\">", code_tag("rust"));
+        assert_eq!(
+            "<code class=\"language-&quot;&gt;&lt;script&gt;\">",
+            code_tag("\"><script>")
+        );
+    }
+}
@@ -0,0 +1,373 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+
+use ::image::codecs::gif::GifDecoder;
+use ::image::imageops::FilterType;
+use ::image::{AnimationDecoder, DynamicImage, ImageError, ImageFormat, ImageReader};
+
+use crate::asset_filter::hash;
+
+/// why an image couldn't be resized, apart from `Io` it's copied as it is
+#[derive(Debug)]
+pub enum ResizeError {
+    /// it couldn't be read or written in it's format e.g. a broken file
+    Image(ImageError),
+    /// an animated gif, only it's first frame would be kept
+    Animated,
+    /// the cache couldn't be read or written
+    Io(io::Error),
+}
+
+impl Display for ResizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResizeError::Image(e) => write!(f, "{}", e),
+            ResizeError::Animated => f.write_str("animated gifs aren't resized"),
+            ResizeError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// reading from memory can only fail if the image is broken, so it's never `Io`
+impl From<ImageError> for ResizeError {
+    fn from(e: ImageError) -> Self {
+        ResizeError::Image(e)
+    }
+}
+
+impl From<io::Error> for ResizeError {
+    fn from(e: io::Error) -> Self {
+        ResizeError::Io(e)
+    }
+}
+
+/// a copy of an image at one of the widths
+#[derive(Debug, PartialEq)]
+pub struct Variant {
+    pub width: u32,
+    /// the format it was written in e.g. `png` or `webp`
+    pub extension: &'static str,
+    pub bytes: Vec<u8>,
+    /// where it's kept between builds
+    pub cached: PathBuf,
+}
+
+/// the format of a png, jpeg, gif or webp from the start of the file
+fn format(bytes: &[u8]) -> Option<ImageFormat> {
+    ::image::guess_format(bytes).ok().filter(|format| {
+        matches!(
+            format,
+            ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Gif | ImageFormat::WebP
+        )
+    })
+}
+
+/// the width and height of a png, jpeg, gif or webp from it's header
+pub fn dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let format = format(bytes)?;
+    ImageReader::with_format(Cursor::new(bytes), format)
+        .into_dimensions()
+        .ok()
+}
+
+/// `cats.png` to `cats-480.webp`
+pub fn variant_name(name: &Path, width: u32, extension: &str) -> PathBuf {
+    let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+    name.with_file_name(format!("{}-{}.{}", stem, width, extension))
+}
+
+/// only the first frame of a gif is decoded so animated ones aren't
+fn decode(bytes: &[u8], format: ImageFormat) -> Result<DynamicImage, ResizeError> {
+    if format == ImageFormat::Gif
+        && GifDecoder::new(Cursor::new(bytes))?
+            .into_frames()
+            .take(2)
+            .count()
+            > 1
+    {
+        return Err(ResizeError::Animated);
+    }
+    Ok(::image::load_from_memory_with_format(bytes, format)?)
+}
+
+/// jpegs can't have transparency and webps have to be 8 bit
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, ImageError> {
+    let image = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()),
+        ImageFormat::WebP if image.color().has_alpha() => {
+            DynamicImage::ImageRgba8(image.to_rgba8())
+        }
+        ImageFormat::WebP => DynamicImage::ImageRgb8(image.to_rgb8()),
+        _ => image.clone(),
+    };
+    let mut bytes = Cursor::new(Vec::new());
+    image.write_to(&mut bytes, format)?;
+    Ok(bytes.into_inner())
+}
+
+/// a copy of the image for each of the `widths` that are smaller than it in the same format,
+/// and with `webp` a webp of each along with a full size one. They are kept in `cache` by a
+/// hash of the image so they are only resized when the image changes. Files that aren't images
+/// have none
+pub fn variants(
+    bytes: &[u8],
+    widths: &[u32],
+    webp: bool,
+    cache: &Path,
+) -> Result<(Vec<Variant>, Vec<Variant>), ResizeError> {
+    let (format, (width, height)) = match (format(bytes), dimensions(bytes)) {
+        (Some(format), Some(size)) => (format, size),
+        _ => return Ok((Vec::new(), Vec::new())),
+    };
+    let key = format!("{:016x}", hash(bytes));
+
+    let mut widths: Vec<u32> = widths
+        .iter()
+        .copied()
+        .filter(|w| *w > 0 && *w < width)
+        .collect();
+    widths.sort_unstable();
+    widths.dedup();
+    // a webp is already the smaller format
+    let webp_widths: Vec<u32> = if webp && format != ImageFormat::WebP {
+        widths
+            .iter()
+            .copied()
+            .chain(std::iter::once(width))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut image = None;
+    let mut resize = |variant_width: u32, output: ImageFormat| -> Result<Variant, ResizeError> {
+        let extension = output.extensions_str()[0];
+        let cached = cache.join(format!("{}-{}.{}", key, variant_width, extension));
+        if let Ok(variant) = std::fs::read(&cached) {
+            return Ok(Variant {
+                width: variant_width,
+                extension,
+                bytes: variant,
+                cached,
+            });
+        }
+
+        let image = match &mut image {
+            Some(image) => image,
+            empty => empty.insert(decode(bytes, format)?),
+        };
+        let variant_height = ((u64::from(height) * u64::from(variant_width) + u64::from(width) / 2)
+            / u64::from(width))
+        .max(1) as u32;
+        let variant = encode(
+            &image.resize_exact(variant_width, variant_height, FilterType::Lanczos3),
+            output,
+        )?;
+        std::fs::create_dir_all(cache)?;
+        std::fs::write(&cached, &variant)?;
+        Ok(Variant {
+            width: variant_width,
+            extension,
+            bytes: variant,
+            cached,
+        })
+    };
+    let same = widths
+        .into_iter()
+        .map(|w| resize(w, format))
+        .collect::<Result<Vec<Variant>, ResizeError>>()?;
+    let webp = webp_widths
+        .into_iter()
+        .map(|w| resize(w, ImageFormat::WebP))
+        .collect::<Result<Vec<Variant>, ResizeError>>()?;
+    Ok((same, webp))
+}
+
+/// removes the copies in `cache` that weren't used by this build, they are from images that
+/// have changed or gone. Only files named like a copy are removed
+pub fn prune(cache: &Path, used: &HashSet<PathBuf>) -> io::Result<()> {
+    if !cache.is_dir() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(cache)? {
+        let path = entry?.path();
+        let is_copy = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.split_once('-'))
+            .is_some_and(|(key, width)| {
+                key.len() == 16
+                    && key.bytes().all(|b| b.is_ascii_hexdigit())
+                    && width.parse::<u32>().is_ok()
+            });
+        if is_copy && path.is_file() && !used.contains(&path) {
+            std::fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod image_tests {
+    use super::*;
+    use ::image::{Frame, Rgba, RgbaImage};
+    use pretty_assertions::assert_eq;
+
+    fn checkerboard(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+        let image = RgbaImage::from_fn(width, height, |x, y| {
+            let v = if (x + y) % 2 == 0 { 255 } else { 0 };
+            Rgba([v, v, v, 255])
+        });
+        encode(&DynamicImage::ImageRgba8(image), format).unwrap()
+    }
+
+    #[test]
+    fn formats() {
+        for format in [
+            ImageFormat::Png,
+            ImageFormat::Jpeg,
+            ImageFormat::Gif,
+            ImageFormat::WebP,
+        ] {
+            assert_eq!(
+                Some((8, 6)),
+                dimensions(&checkerboard(8, 6, format)),
+                "{:?}",
+                format
+            );
+        }
+        assert_eq!(None, dimensions(b"not an image"));
+        assert_eq!(None, dimensions(b"BM not a supported image"));
+    }
+
+    #[test]
+    fn resized() {
+        let dir = tempfile::tempdir().unwrap();
+        let png = checkerboard(8, 6, ImageFormat::Png);
+        let (small, webp) = variants(&png, &[4], false, dir.path()).unwrap();
+        assert!(webp.is_empty());
+        assert_eq!("png", small[0].extension);
+        // every 2x2 block of the checkerboard is close to grey
+        let pixels = ::image::load_from_memory(&small[0].bytes)
+            .unwrap()
+            .to_rgba8();
+        assert_eq!((4, 3), pixels.dimensions());
+        assert!(
+            pixels.pixels().all(|p| (96..=160).contains(&p[0])),
+            "{:?}",
+            pixels
+        );
+
+        let jpeg = checkerboard(8, 6, ImageFormat::Jpeg);
+        let (small, _) = variants(&jpeg, &[4], false, dir.path()).unwrap();
+        assert_eq!("jpg", small[0].extension);
+        assert_eq!(Some((4, 3)), dimensions(&small[0].bytes));
+    }
+
+    #[test]
+    fn webp_variants() {
+        let dir = tempfile::tempdir().unwrap();
+        let jpeg = checkerboard(8, 6, ImageFormat::Jpeg);
+        let (same, webp) = variants(&jpeg, &[4, 16], true, dir.path()).unwrap();
+        // the jpegs are still there for the browsers without webp
+        assert_eq!(
+            vec![(4, "jpg", Some((4, 3)))],
+            same.iter()
+                .map(|v| (v.width, v.extension, dimensions(&v.bytes)))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(4, "webp", Some((4, 3))), (8, "webp", Some((8, 6)))],
+            webp.iter()
+                .map(|v| (v.width, v.extension, dimensions(&v.bytes)))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(ImageFormat::WebP),
+            format(&webp[0].bytes),
+            "{:?}",
+            &webp[0].bytes[..16]
+        );
+
+        // a webp isn't copied twice
+        let original = checkerboard(8, 6, ImageFormat::WebP);
+        let (same, webp) = variants(&original, &[4], true, dir.path()).unwrap();
+        assert_eq!(
+            vec!["webp"],
+            same.iter().map(|v| v.extension).collect::<Vec<_>>()
+        );
+        assert!(webp.is_empty());
+    }
+
+    #[test]
+    fn not_resized() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut gif = Vec::new();
+        {
+            let mut encoder = ::image::codecs::gif::GifEncoder::new(&mut gif);
+            let frame = |v| Frame::new(RgbaImage::from_pixel(8, 6, Rgba([v, v, v, 255])));
+            encoder.encode_frames(vec![frame(0), frame(255)]).unwrap();
+        }
+        assert_eq!(Some((8, 6)), dimensions(&gif));
+        assert!(matches!(
+            variants(&gif, &[4], false, dir.path()),
+            Err(ResizeError::Animated)
+        ));
+
+        let mut broken = checkerboard(8, 6, ImageFormat::Png);
+        let length = broken.len();
+        broken.truncate(length - 20);
+        assert!(matches!(
+            variants(&broken, &[4], false, dir.path()),
+            Err(ResizeError::Image(_))
+        ));
+        assert_eq!(
+            (Vec::new(), Vec::new()),
+            variants(b"not an image", &[4], true, dir.path()).unwrap()
+        );
+    }
+
+    #[test]
+    fn cached_variants() {
+        let dir = tempfile::tempdir().unwrap();
+        let png = checkerboard(8, 6, ImageFormat::Png);
+        let (variants, _) = variants(&png, &[4, 2, 8, 16], false, dir.path()).unwrap();
+        assert_eq!(
+            vec![(2, 2), (4, 3)],
+            variants
+                .iter()
+                .map(|v| dimensions(&v.bytes).unwrap())
+                .collect::<Vec<(u32, u32)>>()
+        );
+        assert_eq!(2, dir.path().read_dir().unwrap().count());
+
+        // a changed file in the cache is used instead of resizing again
+        let key = format!("{:016x}", hash(&png));
+        std::fs::write(dir.path().join(format!("{}-4.png", key)), b"cached").unwrap();
+        let (variants, _) = super::variants(&png, &[4], false, dir.path()).unwrap();
+        assert_eq!(b"cached".to_vec(), variants[0].bytes);
+
+        // only the copies that weren't used are removed
+        std::fs::write(dir.path().join("notes.txt"), b"mine").unwrap();
+        let used = variants.into_iter().map(|v| v.cached).collect();
+        prune(dir.path(), &used).unwrap();
+        let mut left: Vec<String> = dir
+            .path()
+            .read_dir()
+            .unwrap()
+            .map(|f| f.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        left.sort();
+        assert_eq!(
+            vec![format!("{}-4.png", key), String::from("notes.txt")],
+            left
+        );
+
+        assert_eq!(
+            PathBuf::from("blog/cats-480.webp"),
+            variant_name(Path::new("blog/cats.png"), 480, "webp")
+        );
+    }
+}
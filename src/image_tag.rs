@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Arc;
+
+use liquid_core::error::{ResultLiquidExt, ResultLiquidReplaceExt};
+use liquid_core::Expression;
+use liquid_core::Language;
+use liquid_core::Renderable;
+use liquid_core::Result;
+use liquid_core::Runtime;
+use liquid_core::ValueView;
+use liquid_core::{ParseTag, TagReflection, TagTokenIter};
+
+use crate::link_tag::not_found;
+use crate::url_filter::relative_url;
+
+/// an image that was copied to the output and it's resized copies
+#[derive(Debug, Default, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// the url (before the `baseurl`) and width of each copy, smallest first
+    pub srcset: Vec<(String, u32)>,
+    /// the same for the webp copies, they are offered in a `<picture>` so browsers without
+    /// webp still have the original
+    pub webp: Vec<(String, u32)>,
+}
+
+/// every image's name e.g. `cats.png` to what was written out for it
+pub type Images = BTreeMap<String, Image>;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[derive(Debug)]
+struct ImageRenderable {
+    src: Expression,
+    alt: Option<Expression>,
+    images: Arc<Images>,
+    baseurl: String,
+    sizes: String,
+}
+
+impl Renderable for ImageRenderable {
+    fn render_to(&self, writer: &mut dyn Write, runtime: &mut Runtime<'_>) -> Result<()> {
+        let src = self.src.evaluate(runtime)?.to_kstr().to_string();
+        let name = src.trim_start_matches('/');
+        let image = self
+            .images
+            .get(name)
+            .ok_or_else(|| not_found("image", name, self.images.keys()))
+            .trace_with(|| format!("{{% image {} %}}", self.src).into())?;
+        let alt = match &self.alt {
+            Some(alt) => alt.evaluate(runtime)?.to_kstr().to_string(),
+            None => String::new(),
+        };
+
+        let url = |url: &str| escape(&relative_url(&self.baseurl, url));
+        let srcset = |srcset: &[(String, u32)]| {
+            srcset
+                .iter()
+                .map(|(src, width)| format!("{} {}w", url(src), width))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        let src = image
+            .srcset
+            .last()
+            .map_or_else(|| url(name), |(src, _)| url(src));
+        let mut html = format!("<img src=\"{}\"", src);
+        if image.srcset.len() > 1 {
+            html += &format!(
+                " srcset=\"{}\" sizes=\"{}\"",
+                srcset(&image.srcset),
+                escape(&self.sizes)
+            );
+        }
+        html += &format!(
+            " width=\"{}\" height=\"{}\" alt=\"{}\" loading=\"lazy\">",
+            image.width,
+            image.height,
+            escape(&alt)
+        );
+        if !image.webp.is_empty() {
+            html = format!(
+                "<picture><source type=\"image/webp\" srcset=\"{}\" sizes=\"{}\">{}</picture>",
+                srcset(&image.webp),
+                escape(&self.sizes),
+                html
+            );
+        }
+        write!(writer, "{}", html).replace("Failed to render")
+    }
+}
+
+/// `{% image 'cats.png' "a cat" %}` is an `<img>` with the image's size and it's resized
+/// copies as the `srcset`, in a `<picture>` with the webp copies when there are some
+#[derive(Clone, Debug, Default)]
+pub struct ImageTag {
+    images: Arc<Images>,
+    baseurl: String,
+    sizes: String,
+}
+
+impl ImageTag {
+    pub fn new(images: Arc<Images>, baseurl: &str, sizes: &str) -> Self {
+        ImageTag {
+            images,
+            baseurl: baseurl.to_string(),
+            sizes: sizes.to_string(),
+        }
+    }
+}
+
+impl TagReflection for ImageTag {
+    fn tag(&self) -> &'static str {
+        "image"
+    }
+
+    fn description(&self) -> &'static str {
+        "an <img> for an image with it's size and resized copies"
+    }
+}
+
+impl ParseTag for ImageTag {
+    fn parse(
+        &self,
+        mut arguments: TagTokenIter<'_>,
+        _options: &Language,
+    ) -> Result<Box<dyn Renderable>> {
+        let src = arguments
+            .expect_next("image expected.")?
+            .expect_value()
+            .into_result()?;
+        let alt = arguments
+            .next()
+            .map(|alt| alt.expect_value().into_result())
+            .transpose()?;
+        arguments.expect_nothing()?;
+        Ok(Box::new(ImageRenderable {
+            src,
+            alt,
+            images: self.images.clone(),
+            baseurl: self.baseurl.clone(),
+            sizes: self.sizes.clone(),
+        }))
+    }
+
+    fn reflection(&self) -> &dyn TagReflection {
+        self
+    }
+}
+
+#[cfg(test)]
+mod image_tag_tests {
+    use super::*;
+    use crate::util::render_with;
+    use pretty_assertions::assert_eq;
+
+    fn render(template: &str) -> Result<String> {
+        let mut images = Images::new();
+        images.insert(
+            String::from("cats.png"),
+            Image {
+                width: 1600,
+                height: 1200,
+                srcset: vec![
+                    (String::from("cats-480.png"), 480),
+                    (String::from("cats.png"), 1600),
+                ],
+                webp: Vec::new(),
+            },
+        );
+        images.insert(
+            String::from("dog.jpg"),
+            Image {
+                width: 800,
+                height: 600,
+                srcset: vec![(String::from("dog.jpg"), 800)],
+                webp: vec![
+                    (String::from("dog-400.webp"), 400),
+                    (String::from("dog-800.webp"), 800),
+                ],
+            },
+        );
+        let sizes = "(max-width: 600px) 100vw, 50vw";
+        render_with(
+            |builder| builder.tag(ImageTag::new(Arc::new(images), "/docs", sizes)),
+            template,
+            &liquid::object!({ "page": { "title": "\"cats\" & dogs" } }),
+        )
+    }
+
+    #[test]
+    fn image() {
+        assert_eq!(
+            "<img src=\"/docs/cats.png\" srcset=\"/docs/cats-480.png 480w, /docs/cats.png 1600w\" \
+             sizes=\"(max-width: 600px) 100vw, 50vw\" width=\"1600\" height=\"1200\" \
+             alt=\"&quot;cats&quot; &amp; dogs\" loading=\"lazy\">",
+            render("{% image 'cats.png' page.title %}").unwrap()
+        );
+        assert_eq!(
+            "<picture><source type=\"image/webp\" \
+             srcset=\"/docs/dog-400.webp 400w, /docs/dog-800.webp 800w\" \
+             sizes=\"(max-width: 600px) 100vw, 50vw\">\
+             <img src=\"/docs/dog.jpg\" width=\"800\" height=\"600\" alt=\"\" loading=\"lazy\">\
+             </picture>",
+            render("{% image '/dog.jpg' %}").unwrap()
+        );
+    }
+
+    #[test]
+    fn not_found() {
+        let e = render("{% image 'cat.png' 'a cat' %}")
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("requested image=cat.png"), "{}", e);
+        assert!(e.contains("available images=cats.png, dog.jpg"), "{}", e);
+    }
+}
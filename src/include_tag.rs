@@ -1,14 +1,20 @@
 use kstring::KString;
 use liquid::Object;
-use log::warn;
 use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
 
+use crate::error::{CustomError, ErrorKind, Span, WarningKind};
+
+use liquid_core::model::ScalarCow;
+use liquid_core::parser::TryMatchToken;
 use liquid_core::Error;
 use liquid_core::Expression;
 use liquid_core::Language;
 use liquid_core::Renderable;
 use liquid_core::Result;
 use liquid_core::Runtime;
+use liquid_core::Template;
 use liquid_core::ValueView;
 use liquid_core::{error::ResultLiquidExt, Value};
 use liquid_core::{ParseTag, TagReflection, TagTokenIter};
@@ -16,45 +22,39 @@ use liquid_core::{ParseTag, TagReflection, TagTokenIter};
 #[derive(Debug)]
 struct Include {
     partial: Expression,
+    /// jekyll's `{% include nav.html %}` is the variable `nav.html` to liquid, when there's no
+    /// such variable it's the file name instead
+    file: Option<String>,
     vars: Vec<(KString, Expression)>,
 }
 
 impl Renderable for Include {
     fn render_to(&self, writer: &mut dyn Write, runtime: &mut Runtime<'_>) -> Result<()> {
-        let value = self.partial.evaluate(runtime)?;
+        let value = match (self.partial.try_evaluate(runtime), &self.file) {
+            (Some(value), _) => value.into_owned(),
+            (None, Some(file)) => Value::scalar(file.clone()),
+            (None, None) => self.partial.evaluate(runtime)?.into_owned(),
+        };
         if !value.is_scalar() {
             return Error::with_msg("Can only `include` strings")
                 .context("partial", format!("{}", value.source()))
                 .into_err();
         }
 
-        let name = value.to_kstr().into_owned();
-        runtime.run_in_named_scope(name.clone(), |mut scope| -> Result<()> {
-            // if there our additional varaibles creates a include object to access all the varaibles
-            // from e.g. { include 'image.html' path="foo.png" }
-            // then in image.html you could have <img src="{{include.path}}" />
-            if !self.vars.is_empty() {
-                let mut helper_vars = Object::new();
-
-                for (id, val) in &self.vars {
-                    helper_vars.insert(
-                        id.clone(),
-                        val.try_evaluate(scope)
-                            .ok_or_else(|| Error::with_msg("failed to evaluate value"))?
-                            .into_owned(),
-                    );
-                }
-
-                scope.stack_mut().set("include", Value::Object(helper_vars));
-            }
+        let name = match self.file {
+            Some(_) => KString::from(file_name(&value.to_kstr())),
+            None => value.to_kstr().into_owned(),
+        };
+        runtime.run_in_named_scope(name.clone(), |scope| -> Result<()> {
+            set_include_vars(&self.vars, scope)?;
 
             let partial = scope
                 .partials()
-                .get(&name)
+                .get(name.as_str())
                 .trace_with(|| format!("{{% include {} %}}", self.partial).into())?;
 
             partial
-                .render_to(writer, &mut scope)
+                .render_to(writer, scope)
                 .trace_with(|| format!("{{% include {} %}}", self.partial).into())
                 .context_key_with(|| self.partial.to_string().into())
                 .value_with(|| name.to_string().into())
@@ -70,8 +70,192 @@ impl Renderable for Include {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default)]
-pub struct IncludeTag;
+/// if there our additional varaibles creates a include object to access all the varaibles
+/// from e.g. { include 'image.html' path="foo.png" }
+/// then in image.html you could have <img src="{{include.path}}" />
+fn set_include_vars(vars: &[(KString, Expression)], scope: &mut Runtime<'_>) -> Result<()> {
+    if !vars.is_empty() {
+        let mut helper_vars = Object::new();
+
+        for (id, val) in vars {
+            helper_vars.insert(
+                id.clone(),
+                val.try_evaluate(scope)
+                    .ok_or_else(|| Error::with_msg("failed to evaluate value"))?
+                    .into_owned(),
+            );
+        }
+
+        scope.stack_mut().set("include", Value::Object(helper_vars));
+    }
+    Ok(())
+}
+
+/// `{% include_relative file.md %}` reads the file relative to the file it's in (so the article
+/// to start with) each time it's rendered, as it isn't one of the includes that are loaded up front
+struct IncludeRelative {
+    root: PathBuf,
+    file: String,
+    vars: Vec<(KString, Expression)>,
+    language: Arc<Language>,
+}
+
+/// the directories of the `include_relative` files that are being rendered, inner most last
+#[derive(Default)]
+struct RelativeTo(Vec<PathBuf>);
+
+impl std::fmt::Debug for IncludeRelative {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IncludeRelative")
+            .field("file", &self.file)
+            .field("vars", &self.vars)
+            .finish()
+    }
+}
+
+impl IncludeRelative {
+    fn dir(&self, runtime: &mut Runtime<'_>) -> Result<PathBuf> {
+        if let Some(dir) = runtime.get_register_mut::<RelativeTo>().0.last() {
+            return Ok(dir.clone());
+        }
+
+        let page = runtime
+            .stack()
+            .try_get(&[ScalarCow::new("page"), ScalarCow::new("path")])
+            .map(|path| path.to_kstr().to_string())
+            .ok_or_else(|| Error::with_msg("include_relative can only be used in an article"))?;
+        Ok(self
+            .root
+            .join(Path::new(&page).parent().unwrap_or_else(|| Path::new(""))))
+    }
+}
+
+impl Renderable for IncludeRelative {
+    fn render_to(&self, writer: &mut dyn Write, runtime: &mut Runtime<'_>) -> Result<()> {
+        let trace = || format!("{{% include_relative {} %}}", self.file).into();
+        let path = self.dir(runtime).trace_with(trace)?.join(&self.file);
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| {
+                Error::with_msg("unable to read file")
+                    .context("path", path.display().to_string())
+                    .context("cause", e.to_string())
+            })
+            .trace_with(trace)?;
+        let template = liquid_core::parser::parse(&content, &self.language)
+            .map(Template::new)
+            .trace_with(trace)?;
+
+        runtime
+            .get_register_mut::<RelativeTo>()
+            .0
+            .push(path.parent().map(Path::to_path_buf).unwrap_or_default());
+        let result = runtime.run_in_named_scope(self.file.clone(), |scope| -> Result<()> {
+            set_include_vars(&self.vars, scope)?;
+            template.render_to(writer, scope)
+        });
+        runtime.get_register_mut::<RelativeTo>().0.pop();
+        result.trace_with(trace)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct IncludeTag {
+    jekyll: bool,
+}
+
+impl IncludeTag {
+    pub fn new() -> Self {
+        IncludeTag::default()
+    }
+
+    /// `{% include nav.html %}` is the include `nav` rather than the variable `nav.html`
+    pub fn jekyll(mut self, jekyll: bool) -> Self {
+        self.jekyll = jekyll;
+        self
+    }
+}
+
+/// includes are loaded without the `.html`, quotes are optional as it's a file name
+fn file_name(token: &str) -> String {
+    let name = token.trim_matches(|c| c == '"' || c == '\'');
+    name.strip_suffix(".html").unwrap_or(name).to_string()
+}
+
+/// `nav.html` can't be meant as a variable, it's the name of a file
+fn is_file_name(token: &str) -> bool {
+    const EXTENSIONS: [&str; 8] = [
+        "html", "htm", "md", "markdown", "liquid", "txt", "xml", "svg",
+    ];
+    token
+        .rsplit_once('.')
+        .is_some_and(|(name, extension)| !name.is_empty() && EXTENSIONS.contains(&extension))
+}
+
+/// `{% include nav.html %}` without `jekyll_includes` is the variable `nav.html`, it's still
+/// included as the file but it's a warning pointing at it as it's probably a jekyll template
+pub fn jekyll_includes(source: &str, path: &Path) -> Vec<CustomError> {
+    let mut warnings = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let mut offset = 0;
+        while let Some(start) = line[offset..].find("{%").map(|start| offset + start + 2) {
+            offset = start;
+            let rest = line[start..].trim_start_matches('-').trim_start();
+            let name = match rest.strip_prefix("include") {
+                Some(name) if name.starts_with(char::is_whitespace) => name.trim_start(),
+                _ => continue,
+            };
+            let token = name
+                .split(|c: char| c.is_whitespace() || c == '%' || c == '}')
+                .next()
+                .unwrap_or("")
+                .trim_end_matches('-');
+            if !is_file_name(token) || token.starts_with(['"', '\'']) {
+                continue;
+            }
+            let token_start = line.len() - name.len();
+            warnings.push(
+                CustomError::new(
+                    ErrorKind::Liquid,
+                    format!("potential jekyll include tag found: {}", token),
+                )
+                .path(path)
+                .span(Span {
+                    line: i + 1,
+                    start: token_start,
+                    end: token_start + token.len(),
+                    text: line.to_string(),
+                })
+                .help(format!("quote it as '{}' or set `jekyll_includes`", token))
+                .warning(WarningKind::JekyllInclude),
+            );
+        }
+    }
+    warnings
+}
+
+/// `foo="bar" size=page.size` after the name of the include
+fn parse_vars(arguments: &mut TagTokenIter<'_>) -> Result<Vec<(KString, Expression)>> {
+    let mut vars: Vec<(KString, Expression)> = Vec::new();
+    while let Ok(next) = arguments.expect_next("") {
+        let id = next.expect_value().into_result()?.to_string();
+
+        arguments
+            .expect_next("expected string")?
+            .expect_str("=")
+            .into_result_custom_msg("expected '=' to be used for the assignment")?;
+
+        vars.push((
+            id.into(),
+            arguments
+                .expect_next("expected expression/value")?
+                .expect_value()
+                .into_result()?,
+        ));
+    }
+
+    arguments.expect_nothing()?;
+    Ok(vars)
+}
 
 impl TagReflection for IncludeTag {
     fn tag(&self) -> &'static str {
@@ -91,43 +275,86 @@ impl ParseTag for IncludeTag {
     ) -> Result<Box<dyn Renderable>> {
         let name = arguments.expect_next("Identifier or literal expected.")?;
 
-        let name = name.expect_value().into_result()?;
-        match name.clone() {
-            Expression::Variable(v) => {
-                warn!(
-                    "potential jekyll include tag found: {0} (fix add '{0}')",
-                    v.to_string()
-                        .replace("[", ".")
-                        .replace("]", "")
-                        .replace("\"", "")
-                );
-            }
-            Expression::Literal(_) => {}
+        if self.jekyll {
+            // `{% include page.config.layout %}` is still the variable when it's defined
+            let file = file_name(name.as_str());
+            let partial = match name.expect_value() {
+                TryMatchToken::Matches(partial @ Expression::Variable(_)) => partial,
+                _ => Expression::with_literal(file.clone()),
+            };
+            return Ok(Box::new(Include {
+                partial,
+                file: Some(file),
+                vars: parse_vars(&mut arguments)?,
+            }));
         }
 
-        let mut vars: Vec<(KString, Expression)> = Vec::new();
-        while let Ok(next) = arguments.expect_next("") {
-            let id = next.expect_value().into_result()?.to_string();
+        // a file name is found by `jekyll_includes` up front as it knows where it is
+        let file = Some(file_name(name.as_str())).filter(|_| is_file_name(name.as_str()));
+        let name = name.expect_value().into_result()?;
+        Ok(Box::new(Include {
+            partial: name,
+            file,
+            vars: parse_vars(&mut arguments)?,
+        }))
+    }
 
-            arguments
-                .expect_next("expected string")?
-                .expect_str("=")
-                .into_result_custom_msg("expected '=' to be used for the assignment")?;
-
-            vars.push((
-                id.into(),
-                arguments
-                    .expect_next("expected expression/value")?
-                    .expect_value()
-                    .into_result()?,
-            ));
+    fn reflection(&self) -> &dyn TagReflection {
+        self
+    }
+}
+
+/// `{% include_relative notes.md %}`, `page.path` is from the root of the site so that's
+/// needed to find the file
+#[derive(Clone, Debug, Default)]
+pub struct IncludeRelativeTag {
+    root: PathBuf,
+}
+
+impl IncludeRelativeTag {
+    pub fn new(root: &Path) -> Self {
+        IncludeRelativeTag {
+            root: root.to_path_buf(),
         }
+    }
+}
 
-        arguments.expect_nothing()?;
+impl TagReflection for IncludeRelativeTag {
+    fn tag(&self) -> &'static str {
+        "include_relative"
+    }
 
-        Ok(Box::new(Include {
-            partial: name,
-            vars,
+    fn description(&self) -> &'static str {
+        ""
+    }
+}
+
+impl ParseTag for IncludeRelativeTag {
+    fn parse(
+        &self,
+        mut arguments: TagTokenIter<'_>,
+        options: &Language,
+    ) -> Result<Box<dyn Renderable>> {
+        let token = arguments.expect_next("file name expected.")?;
+        let file = token
+            .as_str()
+            .trim_matches(|c| c == '"' || c == '\'')
+            .to_string();
+        // like jekyll the file can't be outside of the directory it's in
+        if Path::new(&file)
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(
+                token.raise_custom_error("include_relative can't use `..` or an absolute path")
+            );
+        }
+
+        Ok(Box::new(IncludeRelative {
+            root: self.root.clone(),
+            file,
+            vars: parse_vars(&mut arguments)?,
+            language: Arc::new(options.clone()),
         }))
     }
 
@@ -135,3 +362,145 @@ impl ParseTag for IncludeTag {
         self
     }
 }
+
+#[cfg(test)]
+mod include_tag_tests {
+    use super::*;
+    use crate::util::render_with;
+    use pretty_assertions::assert_eq;
+
+    type Partials = liquid::partials::EagerCompiler<liquid::partials::InMemorySource>;
+
+    fn render(template: &str, jekyll: bool, globals: &liquid::Object) -> Result<String> {
+        render_from(Path::new(""), template, jekyll, globals)
+    }
+
+    fn render_from(
+        root: &Path,
+        template: &str,
+        jekyll: bool,
+        globals: &liquid::Object,
+    ) -> Result<String> {
+        let mut partials = Partials::empty();
+        partials.add("nav", "<nav>{{ include.title }}</nav>");
+        let tags = |builder: liquid::ParserBuilder| {
+            builder
+                .partials(partials)
+                .tag(IncludeTag::new().jekyll(jekyll))
+                .tag(IncludeRelativeTag::new(root))
+        };
+        render_with(tags, template, globals)
+    }
+
+    #[test]
+    fn jekyll_include() {
+        let globals = liquid::object!({ "page": { "title": "cats" } });
+        assert_eq!(
+            "<nav>cats</nav>",
+            render("{% include nav.html title=page.title %}", true, &globals).unwrap()
+        );
+        assert_eq!(
+            "<nav>cats</nav>",
+            render("{% include 'nav' title=page.title %}", true, &globals).unwrap()
+        );
+        assert_eq!(
+            "<nav>cats</nav>",
+            render("{% include 'nav' title=page.title %}", false, &globals).unwrap()
+        );
+
+        // a variable that's defined is used rather than being a file name
+        let globals =
+            liquid::object!({ "page": { "title": "cats", "config": { "layout": "nav.html" } } });
+        assert_eq!(
+            "<nav>cats</nav>",
+            render(
+                "{% include page.config.layout title=page.title %}",
+                true,
+                &globals
+            )
+            .unwrap()
+        );
+        let globals = liquid::object!({ "page": { "config": { "layout": "nope" } } });
+        assert!(render("{% include page.config.layout %}", true, &globals).is_err());
+    }
+
+    #[test]
+    fn bare_file_names() {
+        let warnings = jekyll_includes(
+            "<nav>\n{% include 'nav.html' %}{%- include nav.html title=page.title -%}\n\
+             {% include page.config.layout %}{% include posts/list.md %}",
+            Path::new("default.html"),
+        );
+        let spans: Vec<(usize, usize, usize)> = warnings
+            .iter()
+            .map(|e| {
+                let span = e.span.as_ref().unwrap();
+                (span.line, span.start, span.end)
+            })
+            .collect();
+        assert_eq!(vec![(2, 36, 44), (3, 43, 56)], spans);
+        assert_eq!(Some(PathBuf::from("default.html")), warnings[0].path);
+
+        // it's still included as the file
+        let globals = liquid::object!({ "page": { "title": "cats" } });
+        assert_eq!(
+            "<nav>cats</nav>",
+            render("{% include nav.html title=page.title %}", false, &globals).unwrap()
+        );
+    }
+
+    #[test]
+    fn include_relative() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("inner")).unwrap();
+        std::fs::write(
+            dir.path().join("snippet.md"),
+            "{{ include.animal }} {% include_relative \"inner/deep.txt\" %}",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("inner/deep.txt"),
+            "{{ page.title }} {% include_relative other.txt %}",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("inner/other.txt"), "dogs").unwrap();
+
+        let globals = liquid::object!({
+            "page": {
+                "title": "cats",
+                "path": "a.md",
+            }
+        });
+        assert_eq!(
+            "bird cats dogs",
+            render_from(
+                dir.path(),
+                "{% include_relative snippet.md animal=\"bird\" %}",
+                false,
+                &globals
+            )
+            .unwrap()
+        );
+
+        let e = render_from(
+            dir.path(),
+            "{% include_relative nothere.md %}",
+            false,
+            &globals,
+        )
+        .unwrap_err();
+        assert!(e.to_string().contains("unable to read file"), "{}", e);
+
+        std::fs::write(dir.path().join("secret"), "password").unwrap();
+        for file in ["../secret", "inner/../../secret", "/etc/passwd"] {
+            let e = render_from(
+                &dir.path().join("inner"),
+                &format!("{{% include_relative '{}' %}}", file),
+                false,
+                &globals,
+            )
+            .unwrap_err();
+            assert!(e.to_string().contains("can't use `..`"), "{}", e);
+        }
+    }
+}
@@ -1,40 +1,155 @@
 use liquid::ValueView;
-use liquid_core::{to_value, Display_filter, Filter, FilterReflection, ParseFilter};
-use log::debug;
+use liquid_core::{
+    to_value, Display_filter, Error, Expression, Filter, FilterParameters, FilterReflection,
+    FromFilterParameters, ParseFilter, Result, Runtime, Value,
+};
 
-#[derive(Clone, FilterReflection)]
+/// `</script>` in a string would end the script tag the json is in, so `<`, `>` and `&` are
+/// escaped (they can only be inside strings) along with the line separators javascript
+/// doesn't allow in strings
+pub fn escape(json: &str) -> String {
+    let mut escaped = String::with_capacity(json.len());
+    for c in json.chars() {
+        match c {
+            '<' => escaped.push_str("\\u003c"),
+            '>' => escaped.push_str("\\u003e"),
+            '&' => escaped.push_str("\\u0026"),
+            '\u{2028}' => escaped.push_str("\\u2028"),
+            '\u{2029}' => escaped.push_str("\\u2029"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn to_json(input: &dyn ValueView, pretty: bool) -> Result<Value> {
+    let value = input.to_value();
+    let json = if pretty {
+        serde_json::to_string_pretty(&value)
+    } else {
+        serde_json::to_string(&value)
+    }
+    .map_err(|e| Error::with_msg("unable to convert to json").context("cause", e.to_string()))?;
+    Ok(Value::scalar(escape(&json)))
+}
+
+#[derive(Debug, FilterParameters)]
+struct ToJsonArgs {
+    #[parameter(
+        description = "Pretty print the json, true by default",
+        arg_type = "bool"
+    )]
+    pretty: Option<Expression>,
+}
+
+#[derive(Clone, ParseFilter, FilterReflection)]
 #[filter(
     name = "to_json",
-    description = "Serialize a value to json",
+    description = "Serialize a value to json, `to_json: false` for it all on one line",
+    parameters(ToJsonArgs),
     parsed(ToJsonFilter)
 )]
 pub struct ToJson;
 
-#[derive(Debug, Display_filter)]
+#[derive(Debug, FromFilterParameters, Display_filter)]
 #[name = "to_json"]
-struct ToJsonFilter {}
+struct ToJsonFilter {
+    #[parameters]
+    args: ToJsonArgs,
+}
+
+impl Filter for ToJsonFilter {
+    fn evaluate(&self, input: &dyn ValueView, runtime: &Runtime) -> Result<Value> {
+        let args = self.args.evaluate(runtime)?;
+        to_json(input, args.pretty.unwrap_or(true))
+    }
+}
+
+#[derive(Clone, ParseFilter, FilterReflection)]
+#[filter(
+    name = "jsonify",
+    description = "Serialize a value to json on one line like jekyll",
+    parsed(JsonifyFilter)
+)]
+pub struct Jsonify;
+
+#[derive(Debug, Default, Display_filter)]
+#[name = "jsonify"]
+struct JsonifyFilter;
 
-impl ParseFilter for ToJson {
-    fn parse(
-        &self,
-        _arguments: liquid_core::parser::FilterArguments,
-    ) -> liquid_core::Result<Box<dyn Filter>> {
-        Ok(Box::new(ToJsonFilter {}))
+impl Filter for JsonifyFilter {
+    fn evaluate(&self, input: &dyn ValueView, _runtime: &Runtime) -> Result<Value> {
+        to_json(input, false)
     }
+}
+
+#[derive(Clone, ParseFilter, FilterReflection)]
+#[filter(
+    name = "from_json",
+    description = "Parses a json string into a value",
+    parsed(FromJsonFilter)
+)]
+pub struct FromJson;
 
-    fn reflection(&self) -> &dyn FilterReflection {
-        self as &dyn FilterReflection
+#[derive(Debug, Default, Display_filter)]
+#[name = "from_json"]
+struct FromJsonFilter;
+
+impl Filter for FromJsonFilter {
+    fn evaluate(&self, input: &dyn ValueView, _runtime: &Runtime) -> Result<Value> {
+        if input.is_nil() {
+            return Ok(Value::Nil);
+        }
+        let json: serde_json::Value = serde_json::from_str(&input.to_kstr())
+            .map_err(|e| Error::with_msg("invalid json").context("cause", e.to_string()))?;
+        to_value(&json)
     }
 }
 
-impl Filter for ToJsonFilter {
-    fn evaluate(
-        &self,
-        input: &dyn ValueView,
-        _runtime: &liquid_core::Runtime,
-    ) -> liquid_core::Result<liquid_core::Value> {
-        let output = serde_json::to_string_pretty(&input.to_value()).unwrap();
-        // debug!("to_json: {}",output);
-        Ok(to_value(&output)?)
+#[cfg(test)]
+mod json_filter_tests {
+    use super::*;
+    use crate::util::render_with;
+    use pretty_assertions::assert_eq;
+
+    fn render(template: &str) -> liquid_core::Result<String> {
+        render_with(
+            |builder| builder.filter(ToJson).filter(Jsonify).filter(FromJson),
+            template,
+            &liquid::object!({
+                "page": { "title": "</script><b>cats & dogs</b>", "tags": ["a", "b"] },
+                "data": "{\"cats\": [1, 2], \"name\": \"mole\"}",
+            }),
+        )
+    }
+
+    #[test]
+    fn to_json_compact() {
+        assert_eq!(
+            "[\"a\",\"b\"] [\"a\",\"b\"]",
+            render("{{ page.tags | to_json: false }} {{ page.tags | jsonify }}").unwrap()
+        );
+        assert_eq!(
+            "[\n  \"a\",\n  \"b\"\n]",
+            render("{{ page.tags | to_json }}").unwrap()
+        );
+    }
+
+    #[test]
+    fn to_json_escapes_script() {
+        assert_eq!(
+            "\"\\u003c/script\\u003e\\u003cb\\u003ecats \\u0026 dogs\\u003c/b\\u003e\"",
+            render("{{ page.title | jsonify }}").unwrap()
+        );
+    }
+
+    #[test]
+    fn from_json() {
+        assert_eq!(
+            "mole 2",
+            render("{% assign d = data | from_json %}{{ d.name }} {{ d.cats | last }}").unwrap()
+        );
+        let e = render("{{ '{\"cats\": ' | from_json }}").unwrap_err();
+        assert!(e.to_string().contains("invalid json"), "{}", e);
     }
 }
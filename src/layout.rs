@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::error::{CustomError, ErrorKind, Span};
+use crate::parse::{
+    parse_error, parse_key, parse_value_boolean, parse_value_list, parse_value_string, value_span,
+};
+use crate::util::suggestions;
+
+/// a layout template, it can have front matter like an article e.g. `layout: default` to be
+/// wrapped in another layout. The rest of the front matter is `layout.*` in the template
+#[derive(Debug)]
+pub struct Layout {
+    pub path: PathBuf,
+    pub parent: Option<String>,
+    pub front_matter: liquid::Object,
+    /// the `layout:` line so that loops can point at it
+    parent_span: Option<Span>,
+}
+
+/// a layout and each of it's parents by name, inner most first
+pub type Chain<'a> = Vec<(&'a str, &'a Layout)>;
+
+/// every layout that has been loaded by name
+#[derive(Debug, Default)]
+pub struct Layouts {
+    layouts: HashMap<String, Layout>,
+}
+
+/// splits the front matter off the layout, layouts without any are returned as they are.
+/// values are strings unless they look like a list `[a, b]` or a boolean
+pub fn parse_layout(content: &str, path: &Path) -> Result<(Layout, String), CustomError> {
+    let mut layout = Layout {
+        path: path.to_path_buf(),
+        parent: None,
+        front_matter: liquid::Object::new(),
+        parent_span: None,
+    };
+
+    let mut lines = content.split_inclusive('\n');
+    if lines.next().map(str::trim_end) != Some("---") {
+        return Ok((layout, content.to_string()));
+    }
+
+    let mut line_n = 2;
+    let mut reached_end = false;
+    let mut body = String::new();
+    for line in lines {
+        if reached_end {
+            body += line;
+            continue;
+        }
+
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line == "---" {
+            reached_end = true;
+        } else {
+            let (key, rest) = parse_key(line, path, line, line_n)?;
+            let rest = rest.trim();
+            let value = if rest.starts_with('[') {
+                liquid::model::Value::array(
+                    parse_value_list(rest, path, line, line_n)?
+                        .into_iter()
+                        .map(liquid::model::Value::scalar),
+                )
+            } else if rest == "true" || rest == "false" {
+                liquid::model::Value::scalar(parse_value_boolean(rest, path, line, line_n)?)
+            } else {
+                liquid::model::Value::scalar(
+                    parse_value_string(rest, path, line, line_n)?.to_string(),
+                )
+            };
+
+            if key.trim() == "layout" {
+                let parent = parse_value_string(rest, path, line, line_n)?;
+                layout.parent_span = Some(value_span(line, rest, line_n));
+                layout.parent = Some(parent.to_string());
+            }
+            layout
+                .front_matter
+                .insert(key.trim().to_string().into(), value);
+            line_n += 1;
+        }
+    }
+
+    if !reached_end {
+        return Err(parse_error(
+            ErrorKind::InvalidConfig,
+            "no at '---' for the last line of the configuration",
+            path,
+            "",
+            0,
+            0,
+            line_n,
+        ));
+    }
+    Ok((layout, body))
+}
+
+impl Layouts {
+    pub fn insert(&mut self, name: String, layout: Layout) {
+        self.layouts.insert(name, layout);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layouts.is_empty()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.layouts.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// the layout and then each of it's parents, loops and parents that don't exist are errors
+    /// so they are found before anything is rendered
+    pub fn chain<'a>(&'a self, name: &'a str) -> Result<Chain<'a>, CustomError> {
+        let mut chain: Chain = Vec::new();
+        let mut next = Some(name);
+        while let Some(name) = next {
+            let layout = match self.layouts.get(name) {
+                Some(layout) => layout,
+                None => break,
+            };
+
+            if let Some(i) = chain.iter().position(|(n, _)| *n == name) {
+                // the loop starts at the same layout whichever one it was found from,
+                // so it's the same error for every layout and article that uses it
+                let mut cycle = chain[i..].to_vec();
+                let first = (0..cycle.len()).min_by_key(|i| cycle[*i].0).unwrap_or(0);
+                cycle.rotate_left(first);
+                let names: Vec<&str> = cycle
+                    .iter()
+                    .map(|(n, _)| *n)
+                    .chain(std::iter::once(cycle[0].0))
+                    .collect();
+                let (_, last) = cycle[cycle.len() - 1];
+                let mut e = CustomError::new(
+                    ErrorKind::InvalidConfig,
+                    format!("layout loop: {}", names.join(" -> ")),
+                )
+                .path(&last.path);
+                if let Some(span) = &last.parent_span {
+                    e = e.span(span.clone());
+                }
+                return Err(e);
+            }
+
+            chain.push((name, layout));
+            next = layout.parent.as_deref();
+            if let Some(parent) = next {
+                self.check(parent, &layout.path, layout.parent_span.as_ref())?;
+            }
+        }
+        Ok(chain)
+    }
+
+    /// the chain of every layout, so that loops and missing parents are reported once
+    /// before any articles are rendered instead of for each article that uses them
+    pub fn check_chains(&self) -> Vec<CustomError> {
+        let mut errors: Vec<CustomError> = Vec::new();
+        for name in self.names() {
+            if let Err(e) = self.chain(name) {
+                if !errors.contains(&e) {
+                    errors.push(e);
+                }
+            }
+        }
+        errors
+    }
+
+    /// an error pointing at where the layout was asked for if it doesn't exist
+    pub fn check(&self, name: &str, path: &Path, span: Option<&Span>) -> Result<(), CustomError> {
+        if self.layouts.contains_key(name) {
+            return Ok(());
+        }
+
+        let mut e = CustomError::new(
+            ErrorKind::InvalidValue,
+            format!("layout '{}' not found", name),
+        )
+        .path(path);
+        if let Some(span) = span {
+            e = e.span(span.clone());
+        }
+        if let Some(closest) = suggestions(name, self.layouts.keys().map(String::as_str)).first() {
+            e = e.help(format!("did you mean `{}`?", closest));
+        }
+        Err(e)
+    }
+
+    /// whether the layout is wrapped in another one
+    pub fn has_parent(&self, name: &str) -> bool {
+        matches!(self.layouts.get(name), Some(layout) if layout.parent.is_some())
+    }
+
+    /// `layout_config` for each layout in the chain, like jekyll the front matter of the layouts
+    /// it wraps overrides it's own so a parent can use values set by it's children
+    pub fn front_matter(chain: &[(&str, &Layout)]) -> Vec<liquid::Object> {
+        let mut merged = liquid::Object::new();
+        chain
+            .iter()
+            .map(|(_, layout)| {
+                let mut object = layout.front_matter.clone();
+                object.extend(merged.clone());
+                merged = object.clone();
+                object
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod layout_tests {
+    use super::*;
+    use liquid::ValueView;
+    use pretty_assertions::assert_eq;
+
+    fn layouts(layouts: &[(&str, &str)]) -> Layouts {
+        let mut all = Layouts::default();
+        for (name, content) in layouts {
+            let (layout, _) = parse_layout(content, Path::new(&format!("{}.html", name))).unwrap();
+            all.insert(name.to_string(), layout);
+        }
+        all
+    }
+
+    #[test]
+    fn front_matter() {
+        let (layout, body) = parse_layout(
+            "---\nlayout: default\nsidebar: true\ntags: [a, b]\n---\n<p>{{ content }}</p>",
+            Path::new("post.html"),
+        )
+        .unwrap();
+        assert_eq!(Some(String::from("default")), layout.parent);
+        assert_eq!("<p>{{ content }}</p>", body);
+        assert_eq!(
+            "true",
+            layout
+                .front_matter
+                .get("sidebar")
+                .unwrap()
+                .to_kstr()
+                .as_str()
+        );
+        assert_eq!(
+            2,
+            layout
+                .front_matter
+                .get("tags")
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .size()
+        );
+
+        let (layout, body) = parse_layout("<p>{{ content }}</p>", Path::new("a.html")).unwrap();
+        assert!(layout.parent.is_none());
+        assert_eq!("<p>{{ content }}</p>", body);
+    }
+
+    #[test]
+    fn chain() {
+        let all = layouts(&[
+            ("default", "{{ content }}"),
+            ("page", "---\nlayout: default\n---\n{{ content }}"),
+            ("post", "---\nlayout: page\n---\n{{ content }}"),
+        ]);
+        let names: Vec<&str> = all.chain("post").unwrap().iter().map(|(n, _)| *n).collect();
+        assert_eq!(vec!["post", "page", "default"], names);
+        assert!(all.chain("nothere").unwrap().is_empty());
+    }
+
+    #[test]
+    fn chain_loop() {
+        let all = layouts(&[
+            ("a", "---\nlayout: b\n---\n"),
+            ("b", "---\nlayout: c\n---\n"),
+            ("c", "---\nlayout: b\n---\n"),
+        ]);
+        let e = all.chain("a").unwrap_err();
+        assert_eq!("layout loop: b -> c -> b", e.message);
+        assert_eq!(Some(PathBuf::from("c.html")), e.path);
+        assert_eq!(2, e.span.unwrap().line);
+    }
+
+    #[test]
+    fn check_chains() {
+        let all = layouts(&[
+            ("a", "---\nlayout: b\n---\n"),
+            ("b", "---\nlayout: c\n---\n"),
+            ("c", "---\nlayout: b\n---\n"),
+            ("default", "{{ content }}"),
+            ("page", "---\nlayout: defualt\n---\n"),
+            ("post", "---\nlayout: page\n---\n"),
+        ]);
+        let errors: Vec<String> = all.check_chains().into_iter().map(|e| e.message).collect();
+        assert_eq!(
+            vec!["layout loop: b -> c -> b", "layout 'defualt' not found"],
+            errors
+        );
+    }
+
+    #[test]
+    fn missing_parent() {
+        let all = layouts(&[
+            ("default", "{{ content }}"),
+            ("page", "---\nlayout: defualt\n---\n"),
+        ]);
+        let e = all.chain("page").unwrap_err();
+        assert_eq!("layout 'defualt' not found", e.message);
+        assert_eq!(Some(String::from("did you mean `default`?")), e.help);
+    }
+
+    #[test]
+    fn merged_front_matter() {
+        let all = layouts(&[
+            ("default", "---\ncolour: red\nsidebar: false\n---\n"),
+            ("page", "---\nlayout: default\nsidebar: true\n---\n"),
+        ]);
+        let objects = Layouts::front_matter(&all.chain("page").unwrap());
+        assert!(objects[0].get("colour").is_none());
+        assert_eq!(
+            "true",
+            objects[1].get("sidebar").unwrap().to_kstr().as_str()
+        );
+        assert_eq!("red", objects[1].get("colour").unwrap().to_kstr().as_str());
+    }
+}
@@ -1,16 +1,40 @@
+// the older tests are written this way
+#![cfg_attr(test, allow(clippy::assertions_on_constants, clippy::partialeq_to_none))]
+
 use log::{debug, error, info, warn};
 use std::fs::read_to_string;
 use std::fs::File;
-use std::io::{prelude::*, BufReader};
+use std::io::BufReader;
 use std::path::{Path, PathBuf};
-use std::{collections::HashMap, fs};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+};
 
 pub mod article;
+pub mod config;
 pub mod error;
+pub mod markdown;
 pub mod parse;
 
+mod array_filter;
+mod asset_filter;
+mod backtrace;
+mod date_filter;
+mod highlight;
+mod image;
+mod image_tag;
 mod include_tag;
 mod json_filter;
+mod layout;
+mod link_check;
+mod link_tag;
+mod minify;
+mod protect;
+mod sass;
+mod text_filter;
+mod toc;
+mod url_filter;
 mod util;
 
 pub type Partials = liquid::partials::EagerCompiler<liquid::partials::InMemorySource>;
@@ -18,24 +42,95 @@ pub type Partials = liquid::partials::EagerCompiler<liquid::partials::InMemorySo
 pub struct Build<'a> {
     includes: Partials,
     articles: Vec<article::Article>,
-    layouts: Vec<String>,
+    config: config::SiteConfig,
+    layouts: layout::Layouts,
     output: &'a PathBuf,
 
     backtrace: bool,
     article_paths: Vec<String>,
-    includes_paths: HashMap<String, String>,
+    /// the directory the article directories are in, `{% link %}` paths are from here
+    root: PathBuf,
+    /// name to file for every include and layout so that liquid errors can point at the file
+    templates: HashMap<String, PathBuf>,
+    /// the css and files that were written out for `asset_url`
+    assets: asset_filter::Assets,
+    /// the size and resized copies of each image for `{% image %}`
+    images: image_tag::Images,
+    /// the resized copies that were used from (or added to) `[images] cache`
+    cached: HashSet<PathBuf>,
+    errors: Vec<error::CustomError>,
+    diagnostics: error::Diagnostics,
+}
+
+/// the filters that templates can use, other than the `_exp` ones which parse their
+/// expressions with a parser that has these
+fn filters(
+    builder: liquid::ParserBuilder,
+    config: &config::SiteConfig,
+    assets: &std::sync::Arc<asset_filter::Assets>,
+    markdown: &std::sync::Arc<markdown::Markdown>,
+) -> liquid::ParserBuilder {
+    builder
+        .filter(json_filter::ToJson)
+        .filter(json_filter::Jsonify)
+        .filter(json_filter::FromJson)
+        .filter(url_filter::RelativeUrl::new(config))
+        .filter(url_filter::AbsoluteUrl::new(config))
+        .filter(asset_filter::AssetUrl::new(assets.clone(), &config.baseurl))
+        .filter(liquid_lib::jekyll::Slugify)
+        .filter(liquid_lib::jekyll::Pop)
+        .filter(liquid_lib::jekyll::Push)
+        .filter(liquid_lib::jekyll::Shift)
+        .filter(liquid_lib::jekyll::Unshift)
+        .filter(liquid_lib::jekyll::ArrayToSentenceString)
+        .filter(text_filter::Markdownify::new(markdown.clone()))
+        .filter(text_filter::Smartify)
+        .filter(text_filter::NormalizeWhitespace)
+        // these replace liquid's `date` so it uses the site's timezone and `date_format`
+        .filter(date_filter::DateFormat::new(config))
+        .filter(date_filter::DateToString::new(config))
+        .filter(date_filter::DateToLongString::new(config))
+        .filter(date_filter::DateToXmlschema::new(config))
+        .filter(date_filter::DateToRfc822::new(config))
+        // jekyll's versions replace liquid's `where` and `sort`
+        .filter(array_filter::Where)
+        .filter(array_filter::Find)
+        .filter(array_filter::GroupBy)
+        .filter(array_filter::Sort)
 }
 
 impl<'a> Build<'a> {
     pub fn new(output: &'a PathBuf, backtrace: bool) -> Self {
         Build {
             includes: Partials::empty(),
-            layouts: Vec::new(),
+            layouts: layout::Layouts::default(),
             articles: Vec::new(),
+            config: config::SiteConfig::default(),
             output,
             backtrace,
             article_paths: Vec::new(),
-            includes_paths: HashMap::new(),
+            root: PathBuf::new(),
+            templates: HashMap::new(),
+            assets: asset_filter::Assets::new(),
+            images: image_tag::Images::new(),
+            cached: HashSet::new(),
+            errors: Vec::new(),
+            diagnostics: error::Diagnostics::default(),
+        }
+    }
+
+    pub fn config(mut self, config: config::SiteConfig) -> Self {
+        self.diagnostics.allow(&config.build.allow);
+        self.config = config;
+        self
+    }
+
+    /// `{% include nav.html %}` is a warning unless `jekyll_includes` is on
+    fn jekyll_includes(&self, source: &str, path: &Path) {
+        if !self.config.build.jekyll_includes {
+            for e in include_tag::jekyll_includes(source, path) {
+                self.diagnostics.warn(e);
+            }
         }
     }
 
@@ -45,8 +140,17 @@ impl<'a> Build<'a> {
         if dir.exists() && dir.is_dir() {
             for (file_path, ending) in util::search_dir(dir, false) {
                 if ending == "html" {
-                    if let Ok(content) = util::read_file(&file_path) {
-                        match util::path_file_name_to_string(&file_path) {
+                    // layouts can have front matter
+                    let content = util::read_file(&file_path).and_then(|content| {
+                        self.jekyll_includes(&content, &file_path);
+                        if layout {
+                            layout::parse_layout(&content, &file_path).map(|(l, body)| (Some(l), body))
+                        } else {
+                            Ok((None, content))
+                        }
+                    });
+                    match content {
+                        Ok((front_matter, content)) => match util::path_file_name_to_string(&file_path) {
                             Ok(rel_path) => {
                                 if layout {
                                     info!("new layout {}", rel_path);
@@ -54,30 +158,30 @@ impl<'a> Build<'a> {
                                     info!("new include {}", rel_path);
                                 }
 
-                                // only including error information when backtrace enabled otherwise we just ignore it
-                                if self.backtrace {
-                                    self.includes_paths
-                                        .insert(rel_path.clone(), format!("{:?}", file_path));
-                                }
+                                self.templates.insert(rel_path.clone(), file_path.clone());
 
                                 // layouts and includes both liquid templates
                                 if self.includes.add(&rel_path, content) {
-                                    if layout {
-                                        error!("\"{:?}\" already exists as a layout, note: layouts and includes share the same name", rel_path);
+                                    let message = if layout {
+                                        format!("\"{:?}\" already exists as a layout, note: layouts and includes share the same name", rel_path)
                                     } else {
-                                        error!("\"{:?}\" already exists as a includes, note: layouts and includes share the same name", rel_path);
-                                    }
+                                        format!("\"{:?}\" already exists as a includes, note: layouts and includes share the same name", rel_path)
+                                    };
+                                    self.diagnostics.warn(
+                                        error::CustomError::new(error::ErrorKind::Liquid, message)
+                                            .path(&file_path)
+                                            .warning(error::WarningKind::DuplicateInclude),
+                                    );
                                 }
 
-                                if layout {
+                                if let Some(front_matter) = front_matter {
                                     // this is used to check that articles have a valid layout
-                                    self.layouts.push(rel_path);
+                                    self.layouts.insert(rel_path, front_matter);
                                 }
                             }
-                            Err(e) => error!("{:?}", e),
-                        }
-                    } else {
-                        error!("unable to read file {:?}", file_path);
+                            Err(e) => report(&mut self.errors, e),
+                        },
+                        Err(e) => report(&mut self.errors, e),
                     }
                 }
             }
@@ -88,45 +192,80 @@ impl<'a> Build<'a> {
     }
 
     pub fn articles(mut self, temp: &[&'a PathBuf]) -> Self {
+        self.root = util::common_ancestor(temp);
+        // broken layout chains are reported once here, the articles that use them fail
+        // their layout check with the same error which isn't reported again
+        for e in self.layouts.check_chains() {
+            report(&mut self.errors, e);
+        }
         for dir in temp {
             info!("looking for markdown articles in {:?}", dir);
             if dir.exists() && dir.is_dir() {
-                if self.layouts.is_empty() {
-                    panic!(
-                "empty layout list, please load in layout template files before parsing articles"
-            );
-                } else {
-                    for (f, ending) in util::search_dir(&dir, true) {
-                        if ending == "md" || ending == "markdown" {
-                            if let Ok(cat) = File::open(&f) {
-                                match article::Article::parse(BufReader::new(cat), &f, true) {
-                                    Ok(art) => {
-                                        self.articles.push(art);
-                                        self.article_paths.push(format!("{:?}", &f));
-                                    }
-                                    Err(e) => error!("{:?}", e),
+                for (f, ending) in util::search_dir(dir, true) {
+                    if ending == "md" || ending == "markdown" {
+                        if let Ok(source) = read_to_string(&f) {
+                            self.jekyll_includes(&source, &f);
+                        }
+                        match File::open(&f) {
+                            Ok(cat) => match article::Article::parse(BufReader::new(cat), &f, true)
+                                .map(|art| art.timezone(&self.config.timezone).relative_to(&self.root))
+                                .and_then(|art| art.check_layouts(&self.layouts).map(|_| art))
+                            {
+                                Ok(art) => {
+                                    self.articles.push(art);
+                                    self.article_paths.push(format!("{:?}", &f));
                                 }
-                            } else {
-                                error!("Could not read {:?}", &f);
-                            }
-                        }else if ending == "html" {
-                            if let Ok(cat) = File::open(&f) {
-                                match article::Article::parse(BufReader::new(cat), &f, false) {
-                                    Ok(art) => {
-                                        self.articles.push(art);
-                                        self.article_paths.push(format!("{:?}", &f));
+                                Err(e) => report(&mut self.errors, e),
+                            },
+                            Err(e) => report(
+                                &mut self.errors,
+                                error::CustomError::io("Could not read").path(&f).cause(e),
+                            ),
+                        }
+                    }else if ending == "html" {
+                        if let Ok(source) = read_to_string(&f) {
+                            self.jekyll_includes(&source, &f);
+                        }
+                        match File::open(&f) {
+                            Ok(cat) => match article::Article::parse(BufReader::new(cat), &f, false)
+                                .map(|art| art.timezone(&self.config.timezone).relative_to(&self.root))
+                                .and_then(|art| art.check_layouts(&self.layouts).map(|_| art))
+                            {
+                                Ok(art) => {
+                                    self.articles.push(art);
+                                    self.article_paths.push(format!("{:?}", &f));
+                                }
+                                Err(e) => report(&mut self.errors, e),
+                            },
+                            Err(e) => report(
+                                &mut self.errors,
+                                error::CustomError::io("Could not read").path(&f).cause(e),
+                            ),
+                        }
+                    } else if let Ok(name) = util::path_file_name_to_string(&f) {
+                        // the site's config isn't part of the site
+                        if name == config::CONFIG_FILE {
+                            continue;
+                        }
+                        match fs::read(&f) {
+                            Ok(contents) => {
+                                for output_path in self.asset(Path::new(&name), &contents) {
+                                    info!("copying {:?} to {:?} ", f, output_path);
+                                    if let Err(e) = fs::write(&output_path, &contents) {
+                                        report(
+                                            &mut self.errors,
+                                            error::CustomError::io("Could not copy")
+                                                .path(&f)
+                                                .cause(e),
+                                        );
                                     }
-                                    Err(e) => error!("{:?}", e),
                                 }
-                            } else {
-                                error!("Could not read {:?}", &f);
-                            }
-                        } else if let Ok(name) = util::path_file_name_to_string(&f) {
-                            info!("copying {:?} to {:?} ", f, self.output.join(&name));
-                            match fs::copy(&f, self.output.join(name)) {
-                                Ok(_) => {}
-                                Err(e) => error!("{:?}", e),
+                                self.image(&f, Path::new(&name), &contents);
                             }
+                            Err(e) => report(
+                                &mut self.errors,
+                                error::CustomError::io("Could not read").path(&f).cause(e),
+                            ),
                         }
                     }
                 }
@@ -138,33 +277,40 @@ impl<'a> Build<'a> {
         self
     }
 
-    pub fn sass(self, dir: &'a PathBuf, load_paths: &[&Path]) -> Self {
+    pub fn sass(mut self, dir: &'a PathBuf, load_paths: &[&Path]) -> Self {
         if dir.exists() && dir.is_dir() {
-            for (f, ending) in util::search_dir(dir, true) {
-                if ending == "scss" {
-                    if let Ok(data) = read_to_string(&f) {
-                        debug!("looking for dirs: {:?} paths: {:?}", dir, load_paths);
-                        match grass::from_string(
-                            data,
-                            &grass::Options::default().load_paths(load_paths),
-                        ) {
-                            Ok(css) => {
-                                let mut output_path = self.output.clone();
-                                output_path.push(Path::new(
-                                    &util::path_file_name_to_string(&f)
-                                        .unwrap()
-                                        .replace("scss", "css"),
-                                ));
-                                info!("writing css to {:?}", output_path);
-
-                                let mut file = File::create(output_path).unwrap();
-                                file.write_all(css.as_bytes()).unwrap();
+            // files in sub directories can import from the top of `dir` too
+            let load_paths: Vec<&Path> =
+                std::iter::once(dir.as_path()).chain(load_paths.iter().copied()).collect();
+            for f in sass::entry_points(dir) {
+                debug!("looking for dirs: {:?} paths: {:?}", dir, load_paths);
+                match sass::compile(&f, &load_paths, &self.config.sass) {
+                    Ok(css) => {
+                        let name = sass::output_name(&f, dir);
+                        for output_path in self.asset(&name, css.as_bytes()) {
+                            if let Some(parent) = output_path.parent() {
+                                if let Err(e) = fs::create_dir_all(parent) {
+                                    report(
+                                        &mut self.errors,
+                                        error::CustomError::io("Could not create directory")
+                                            .path(parent)
+                                            .cause(e),
+                                    );
+                                }
+                            }
+                            info!("writing css to {:?}", output_path);
+
+                            if let Err(e) = fs::write(&output_path, &css) {
+                                report(
+                                    &mut self.errors,
+                                    error::CustomError::io("Could not write css")
+                                        .path(&output_path)
+                                        .cause(e),
+                                );
                             }
-                            Err(e) => warn!("parsing sccs {:?} caused {:?}", &f, e),
                         }
-                    } else {
-                        warn!("soemthing went wrong");
                     }
+                    Err(e) => report(&mut self.errors, e),
                 }
             }
         } else {
@@ -173,77 +319,231 @@ impl<'a> Build<'a> {
         self
     }
 
-    pub fn run(self) {
-        info!("run");
-        let mut global_articles: Vec<&liquid::Object> = Vec::new();
-        let mut global_contents: Vec<&str> = Vec::new();
-        let mut global_tags: HashMap<&str, Vec<&str>> = HashMap::new();
-        let mut global_cats: HashMap<&str, Vec<&str>> = HashMap::new();
-
-        let parser = liquid::ParserBuilder::with_stdlib()
-            .partials(self.includes)
-            .tag(include_tag::IncludeTag) // currenlty we are using our own custom include tag
-            
-            .filter(json_filter::ToJson)
-            // however after the PR merges we can optionally have a flag to switch between jekyll and liquid
-            // Intentionally staying with `stdlib::IncludeTag` rather than `jekyll::IncludeTag`
-            // .filter(liquid_lib::jekyll::include)
-            .filter(liquid_lib::jekyll::Slugify)
-            .filter(liquid_lib::jekyll::Pop)
-            .filter(liquid_lib::jekyll::Push)
-            .filter(liquid_lib::jekyll::Shift)
-            .filter(liquid_lib::jekyll::Unshift)
-            .filter(liquid_lib::jekyll::ArrayToSentenceString)
-            .build()
-            .unwrap();
-
-        for obj in &self.articles {
-            global_articles.push(&obj.config_liquid);
-            global_contents.push(&obj.template);
-            for tag in &obj.config.tags {
-                global_tags
-                    .entry(tag)
-                    .or_insert_with(Vec::new)
-                    .push(&obj.url);
+    /// where a copied file or compiled stylesheet is written to, with `[assets] fingerprint`
+    /// it's written with a hash of the contents in the name as well, the original name stays
+    /// for the links in markdown and the `url()`s in css that don't go through `asset_url`
+    fn asset(&mut self, name: &Path, contents: &[u8]) -> Vec<PathBuf> {
+        let mut output_names = vec![name.to_path_buf()];
+        if self.config.assets.fingerprint
+            && asset_filter::is_fingerprinted(name, &self.config.assets.extensions)
+        {
+            output_names.insert(0, asset_filter::fingerprint(name, contents));
+        }
+        self.assets.insert(
+            asset_filter::url_path(name),
+            asset_filter::url_path(&output_names[0]),
+        );
+        output_names
+            .into_iter()
+            .map(|output_name| self.output.join(output_name))
+            .collect()
+    }
+
+    /// resizes a copied image to each of `[images] widths` and records them for `{% image %}`,
+    /// files that aren't images are ignored
+    fn image(&mut self, path: &Path, name: &Path, contents: &[u8]) {
+        let (width, height) = match image::dimensions(contents) {
+            Some(size) => size,
+            None => return,
+        };
+        let cache = self.root.join(&self.config.images.cache);
+        let images = &self.config.images;
+        let (variants, webp) = match image::variants(contents, &images.widths, images.webp, &cache)
+        {
+            Ok(variants) => variants,
+            Err(image::ResizeError::Io(e)) => {
+                report(
+                    &mut self.errors,
+                    error::CustomError::io("Could not resize image").path(path).cause(e),
+                );
+                (Vec::new(), Vec::new())
             }
+            Err(e) => {
+                self.diagnostics.warn(
+                    error::CustomError::new(error::ErrorKind::Image, "Could not resize image")
+                        .path(path)
+                        .cause(e)
+                        .warning(error::WarningKind::Image),
+                );
+                (Vec::new(), Vec::new())
+            }
+        };
 
-            for cat in &obj.config.categories {
-                global_cats
-                    .entry(cat)
-                    .or_insert_with(Vec::new)
-                    .push(&obj.url);
+        let mut srcsets = (Vec::new(), Vec::new());
+        let copies = variants
+            .into_iter()
+            .map(|v| (v, false))
+            .chain(webp.into_iter().map(|v| (v, true)));
+        for (variant, is_webp) in copies {
+            let variant_name = image::variant_name(name, variant.width, variant.extension);
+            for output_path in self.asset(&variant_name, &variant.bytes) {
+                info!("writing {}px copy of {:?} to {:?}", variant.width, path, output_path);
+                if let Err(e) = fs::write(&output_path, &variant.bytes) {
+                    report(
+                        &mut self.errors,
+                        error::CustomError::io("Could not write image")
+                            .path(&output_path)
+                            .cause(e),
+                    );
+                }
             }
+            let url = self.assets[&asset_filter::url_path(&variant_name)].clone();
+            if is_webp {
+                srcsets.1.push((url, variant.width));
+            } else {
+                srcsets.0.push((url, variant.width));
+            }
+            self.cached.insert(variant.cached);
         }
+        let name = asset_filter::url_path(name);
+        let (mut srcset, webp) = srcsets;
+        srcset.push((self.assets[&name].clone(), width));
+        self.images.insert(
+            name,
+            image_tag::Image {
+                width,
+                height,
+                srcset,
+                webp,
+            },
+        );
+    }
 
-        // One of the key things here is that articles is the raw content, that means it's nothing rendered yet
-        // otherwise you would get weird things if you try to depend on something being already being renedered.
-        // Although the cost of that is that we have to do the pre_render() step twice.
-        let global = liquid::object!({
-            "articles": global_articles,
-            "tags": global_tags,
-            "cats": global_cats,
-            "cats_desc": HashMap::<String,String>::new(),
-            "contents": global_contents,
-        });
+    /// renders every article, any failures are logged as they happen and then summarised at
+    /// the end. Returns all of the failures so the caller can exit with an error code
+    pub fn run(mut self) -> Result<(), Vec<error::CustomError>> {
+        info!("run");
+        let mut failures = std::mem::take(&mut self.errors);
 
-        let site = &liquid::object!({
-            "description":"cas",
-            "baseurl":"",
-            "url":"asdfasdf",
-            "title":"foo bar",
-            "pages": global_articles,
-            "categories": global_cats,
-            "email": "foo@gmail.com",
+        // shared with the markdownify filter
+        let markdown = std::sync::Arc::new(match markdown::Markdown::new(&self.config) {
+            Ok(markdown) => markdown,
+            Err(e) => {
+                report(&mut failures, e);
+                markdown::Markdown::default()
+            }
         });
 
+        if self.config.assets.fingerprint {
+            let manifest = self.output.join(&self.config.assets.manifest);
+            info!("writing asset manifest to {:?}", manifest);
+            let json = serde_json::to_string_pretty(&self.assets).unwrap_or_default();
+            if let Err(e) = fs::write(&manifest, json) {
+                report(
+                    &mut failures,
+                    error::CustomError::io("Could not write asset manifest")
+                        .path(&manifest)
+                        .cause(e),
+                );
+            }
+        }
+        // the images have all been resized so the rest of the cache is from old images
+        let cache = self.root.join(&self.config.images.cache);
+        if let Err(e) = image::prune(&cache, &self.cached) {
+            report(
+                &mut failures,
+                error::CustomError::io("Could not clean up the image cache")
+                    .path(&cache)
+                    .cause(e),
+            );
+        }
+        let assets = std::sync::Arc::new(std::mem::take(&mut self.assets));
+        let images = std::sync::Arc::new(std::mem::take(&mut self.images));
+
+        let links = std::sync::Arc::new(link_tag::Links::new(
+            self.articles
+                .iter()
+                .map(|art| (art.path.as_path(), art.url.as_str())),
+            &self.root,
+            &self.config.baseurl,
+        ));
+
+        // `where_exp` and friends parse their expressions with the same filters as the
+        // templates, apart from themselves
+        let expressions = filters(
+            liquid::ParserBuilder::with_stdlib(),
+            &self.config,
+            &assets,
+            &markdown,
+        )
+        .build()
+        .unwrap();
+        let parser = filters(
+            liquid::ParserBuilder::with_stdlib(),
+            &self.config,
+            &assets,
+            &markdown,
+        )
+        .partials(self.includes)
+        // our own include tag so that includes can have parameters, `jekyll_includes`
+        // switches it to jekyll's `{% include nav.html %}` style
+        .tag(
+            include_tag::IncludeTag::new()
+                .jekyll(self.config.build.jekyll_includes),
+        )
+        .tag(include_tag::IncludeRelativeTag::new(&self.root))
+        .tag(link_tag::LinkTag::new(links.clone()))
+        .tag(link_tag::PostUrlTag::new(links))
+        .tag(image_tag::ImageTag::new(
+            images,
+            &self.config.baseurl,
+            &self.config.images.sizes,
+        ))
+        .filter(array_filter::WhereExp::new(expressions.clone()))
+        .filter(array_filter::FindExp::new(expressions.clone()))
+        .filter(array_filter::GroupByExp::new(expressions))
+        .build()
+        .unwrap();
+
+        if let Some(css) = markdown.stylesheet() {
+            let output_path = self.output.join(&self.config.highlight.stylesheet);
+            info!("writing highlight theme to {:?}", output_path);
+            if let Err(e) = fs::write(&output_path, css) {
+                report(
+                    &mut failures,
+                    error::CustomError::io("Could not write highlight theme")
+                        .path(&output_path)
+                        .cause(e),
+                );
+            }
+        }
+
+        // excerpts are rendered first so that they can be included in `global.articles`
+        let (global, site) = globals(&self.articles, &self.config);
+        for art in self.articles.iter_mut() {
+            if let Err(e) = art.excerpt(
+                &global,
+                &site,
+                &parser,
+                &markdown,
+                &self.layouts,
+                &self.config.excerpt_separator,
+            ) {
+                // usually the article fails in the same way, but a tag that is cut in half by
+                // the separator only breaks the excerpt
+                let (e, _) =
+                    backtrace::map_liquid_error(e, &art.path, &self.templates, self.backtrace);
+                self.diagnostics.warn(
+                    e.warning(error::WarningKind::Excerpt)
+                        .help("the excerpt is rendered on it's own, check the excerpt_separator isn't inside a tag"),
+                );
+            }
+        }
+        let (global, site) = globals(&self.articles, &self.config);
+        let site = &site;
+
         if self.articles.is_empty() {
             error!("no articles found");
         }
 
-        info!("layouts: {:?}", self.layouts);
+        info!("layouts: {:?}", self.layouts.names());
 
-        let mut errors: HashMap<String, Vec<String>> = HashMap::new();
-        for (i, art) in self.articles.into_iter().enumerate() {
+        // errors in layouts and includes are the same for every article that uses them,
+        // so they are only shown once along with the articles that were affected
+        let mut errors: HashMap<String, (error::CustomError, Vec<String>)> = HashMap::new();
+        // the size of the html before and after minifying
+        let mut sizes = (0, 0);
+        for art in self.articles.into_iter() {
             //TODO: make this be the url
             let mut output_path = self.output.clone();
             output_path.push(PathBuf::from(if art.url.starts_with('/') {
@@ -253,85 +553,231 @@ impl<'a> Build<'a> {
             }));
             // output_path.push(PathBuf::from(&art.url));
             if art.url.ends_with('/') {
-                fs::create_dir_all(&output_path);
+                if let Err(e) = fs::create_dir_all(&output_path) {
+                    report(
+                        &mut failures,
+                        error::CustomError::io("Could not create directory")
+                            .path(&output_path)
+                            .cause(e),
+                    );
+                }
                 output_path.push("index.html");
             }
             info!("writing to {:?}", output_path);
 
-            match &art.true_render(&global, site, &parser) {
+            let path = art.path.clone();
+            match art.true_render(
+                &global,
+                site,
+                &parser,
+                &markdown,
+                &self.layouts,
+                &self.diagnostics,
+            ) {
                 Ok(output) => {
+                    let output = if self.config.build.minify {
+                        let minified = minify::html(&output);
+                        sizes.0 += output.len();
+                        sizes.1 += minified.len();
+                        minified
+                    } else {
+                        output
+                    };
                     info!("attempting to write too: {:?}", output_path);
-                    let mut file = File::create(output_path).unwrap();
-                    file.write_all(output.as_bytes()).unwrap();
+                    if let Err(e) = fs::write(&output_path, output) {
+                        report(
+                            &mut failures,
+                            error::CustomError::io("Could not write")
+                                .path(&output_path)
+                                .cause(e),
+                        );
+                    }
                 }
-                Err(e) => match e {
-                    error::CustomError::LiquidError(error) => {
-                        error!("{}", error);
-                        // if !error.contains("from: {% include") {
-                        //     error!(
-                        //         "{}file:\n   {}\n",
-                        //         parse_backtrace(error, &HashMap::new()),
-                        //         self.article_paths[i]
-                        //     );
-                        // } else {
-                           
-                        //     errors
-                        //         .entry(format!("Template {}", error))
-                        //         .or_insert_with(Vec::new)
-                        //         .push(self.article_paths[i].clone());
-                        // }
+                Err(e) => {
+                    // liquid errors don't know which article or template they came from
+                    let (e, from_article) =
+                        backtrace::map_liquid_error(e, &path, &self.templates, self.backtrace);
+                    if e.path.as_deref() == Some(path.as_path()) {
+                        report(&mut failures, e);
+                    } else {
+                        let affected = from_article.unwrap_or_else(|| format!("{:?}", path));
+                        // the include chain is part of the key as the same error can be reached
+                        // in different ways e.g. through the layout or the article itself
+                        let key = e
+                            .message
+                            .lines()
+                            .filter(|line| line.starts_with("  = included from"))
+                            .fold(e.summary(), |key, line| key + line);
+                        errors
+                            .entry(key)
+                            .or_insert_with(|| (e, Vec::new()))
+                            .1
+                            .push(affected);
                     }
-
-                    error::CustomError::IOError(e) => error!("{}", e),
-                },
+                }
             }
         }
 
-        if !errors.is_empty() {
-            for (error, affected) in errors {
-                if self.backtrace {
-                    error!(
-                        "{}files that use this template:\n   {}\n",
-                        parse_backtrace(&error, &self.includes_paths),
-                        affected.join(", ")
-                    );
-                } else {
-                    error!(
-                        "{}files that use this template:\n   {}\n",
-                        error,
-                        affected.join(", ")
-                    );
-                }
-            }
+        let mut errors: Vec<(String, (error::CustomError, Vec<String>))> =
+            errors.into_iter().collect();
+        errors.sort_by(|a, b| a.0.cmp(&b.0));
+        for (_, (e, mut affected)) in errors {
+            affected.sort();
+            error!(
+                "{}files that use this template:\n   {}\n",
+                e,
+                affected.join("\n   ")
+            );
+            failures.push(e);
         }
+
+        if self.config.build.check_links {
+            check_output(self.output, &self.config.baseurl, &self.diagnostics);
+        }
+
+        let minified = if self.config.build.minify {
+            Some(sizes)
+        } else {
+            None
+        };
+        summary(
+            failures,
+            self.diagnostics.take(),
+            minified,
+            self.config.build.strict,
+        )
     }
 }
 
-/// provides file path for liquid include errors
-/// note: getting location of the include error in files will be even more messy
-fn parse_backtrace(error: &str, templates: &HashMap<String, String>) -> String {
-    let mut inside_include = false;
-    let mut msg = "".to_string();
-    for line in error.split('\n') {
-        if line.starts_with("from: {% include") {
-            inside_include = true;
-            msg += line;
-        } else if inside_include && line.starts_with("    \"") {
-            if let Some(index) = line[5..].find('"') {
-                if let Some(path) = templates.get(&line[5..5 + index]) {
-                    msg += &format!("{}\n    {} = {}", line, &line[5..5 + index], path);
-                }
-            } else {
-                msg += line;
-            }
-        } else if !inside_include && line == "\twith:" {
-            inside_include = false;
-            msg += line;
-        } else {
-            msg += line;
+/// checks the links in a site that has already been built, like `--check-links` but without
+/// building it first. Broken links are warnings so they only fail in strict mode
+pub fn check_links(
+    output: &Path,
+    config: &config::SiteConfig,
+) -> Result<(), Vec<error::CustomError>> {
+    let diagnostics = error::Diagnostics::default();
+    diagnostics.allow(&config.build.allow);
+    check_output(output, &config.baseurl, &diagnostics);
+    summary(Vec::new(), diagnostics.take(), None, config.build.strict)
+}
+
+/// external links are listed but not followed, there is no network access in a build
+fn check_output(output: &Path, baseurl: &str, diagnostics: &error::Diagnostics) {
+    info!("checking links in {:?}", output);
+    let report = link_check::check_links(output, baseurl);
+    for (url, pages) in &report.external {
+        let pages: Vec<String> = pages
+            .iter()
+            .map(|page| page.strip_prefix(output).unwrap_or(page).display().to_string())
+            .collect();
+        info!("external link (not checked) {} in {}", url, pages.join(", "));
+    }
+    for e in report.broken {
+        diagnostics.warn(e);
+    }
+}
+
+/// logs the error straight away so it's shown in the context of the rest of the build,
+/// the same error from more than one article is only shown once
+fn report(errors: &mut Vec<error::CustomError>, e: error::CustomError) {
+    if !errors.contains(&e) {
+        e.log();
+        errors.push(e);
+    }
+}
+
+/// lists every failure at the end of the build so they aren't lost in the rest of the output,
+/// in strict mode the warnings count as failures too. `minified` is the size of the html
+/// before and after minifying
+fn summary(
+    mut failures: Vec<error::CustomError>,
+    warnings: Vec<error::CustomError>,
+    minified: Option<(usize, usize)>,
+    strict: bool,
+) -> Result<(), Vec<error::CustomError>> {
+    let errors = failures.iter().filter(|e| e.is_error()).count();
+    let mut finished = String::from("build finished");
+    if !strict && !warnings.is_empty() {
+        finished += &format!(" with {} warning(s)", warnings.len());
+    }
+    if let Some((before, after)) = minified.filter(|(before, _)| *before > 0) {
+        finished += &format!(
+            ", minified html from {} to {} bytes saving {:.1}%",
+            before,
+            after,
+            100.0 * before.saturating_sub(after) as f64 / before as f64
+        );
+    }
+    if !strict && !warnings.is_empty() {
+        warn!("{}", finished);
+    } else {
+        info!("{}", finished);
+    }
+    failures.extend(warnings);
+
+    let failed: Vec<&error::CustomError> = failures
+        .iter()
+        .filter(|e| strict || e.is_error())
+        .collect();
+    if failed.is_empty() {
+        return Ok(());
+    }
+
+    let mut msg = if strict {
+        format!(
+            "build failed with {} error(s) and {} warning(s) (--strict):",
+            errors,
+            failed.len() - errors
+        )
+    } else {
+        format!("build failed with {} error(s):", errors)
+    };
+    for e in failed {
+        msg += "\n  ";
+        msg += &e.summary();
+    }
+    error!("{}", msg);
+    Err(failures)
+}
+
+/// builds the `global` and `site` objects that every template gets access too
+fn globals(
+    articles: &[article::Article],
+    config: &config::SiteConfig,
+) -> (liquid::Object, liquid::Object) {
+    let mut global_articles: Vec<&liquid::Object> = Vec::new();
+    let mut global_contents: Vec<&str> = Vec::new();
+    let mut global_tags: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut global_cats: HashMap<&str, Vec<&str>> = HashMap::new();
+
+    for obj in articles {
+        global_articles.push(&obj.config_liquid);
+        global_contents.push(&obj.template);
+        for tag in &obj.config.tags {
+            global_tags.entry(tag).or_default().push(&obj.url);
+        }
+
+        for cat in &obj.config.categories {
+            global_cats.entry(cat).or_default().push(&obj.url);
         }
-        msg += "\n";
     }
 
-    msg
+    // One of the key things here is that articles is the raw content, that means it's nothing rendered yet
+    // otherwise you would get weird things if you try to depend on something being already being renedered.
+    // Although the cost of that is that we have to do the pre_render() step twice.
+    let global = liquid::object!({
+        "articles": global_articles,
+        "tags": global_tags,
+        "cats": global_cats,
+        "cats_desc": HashMap::<String,String>::new(),
+        "contents": global_contents,
+    });
+
+    let mut site = config.to_liquid();
+    site.extend(liquid::object!({
+        "pages": global_articles,
+        "categories": global_cats,
+    }));
+
+    (global, site)
 }
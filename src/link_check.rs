@@ -0,0 +1,499 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::{CustomError, ErrorKind, Span, WarningKind};
+use crate::util::tag_end;
+
+/// an `href` or `src` in a page and the line it's on
+#[derive(Debug, PartialEq)]
+struct Reference {
+    url: String,
+    line: usize,
+    text: String,
+}
+
+/// the links and anchors in a html file
+#[derive(Debug, Default)]
+struct Page {
+    references: Vec<Reference>,
+    ids: HashSet<String>,
+    /// a line is too long to show, as it is once the page is minified
+    minified: bool,
+}
+
+/// longer lines than this aren't shown with a broken link
+const MAX_LINE: usize = 500;
+
+/// a small scanner for the attributes of each tag, comments and the insides of `<script>` and
+/// `<style>` are skipped as they aren't html
+fn parse_html(html: &str) -> Page {
+    let mut page = Page {
+        minified: html.lines().any(|line| line.len() > MAX_LINE),
+        ..Page::default()
+    };
+    let mut offset = 0;
+    while let Some(start) = html[offset..].find('<').map(|i| offset + i) {
+        if html[start..].starts_with("<!--") {
+            offset = html[start..]
+                .find("-->")
+                .map_or(html.len(), |end| start + end + 3);
+            continue;
+        }
+
+        let end = match tag_end(html, start) {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = &html[start + 1..end];
+        let name: String = tag
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        for (key, value, at) in attributes(tag) {
+            match key.as_str() {
+                "href" | "src" => {
+                    let index = start + 1 + at;
+                    let line_start = html[..index].rfind('\n').map_or(0, |i| i + 1);
+                    let line_end = html[index..].find('\n').map_or(html.len(), |i| index + i);
+                    page.references.push(Reference {
+                        url: value,
+                        line: html[..index].matches('\n').count() + 1,
+                        text: html[line_start..line_end].to_string(),
+                    })
+                }
+                "id" => {
+                    page.ids.insert(decode_entities(&value));
+                }
+                "name" if name == "a" => {
+                    page.ids.insert(decode_entities(&value));
+                }
+                _ => {}
+            }
+        }
+
+        offset = end + 1;
+        if name == "script" || name == "style" {
+            let close = format!("</{}", name);
+            offset = html[offset..]
+                .to_ascii_lowercase()
+                .find(&close)
+                .map_or(html.len(), |i| offset + i);
+        }
+    }
+    page
+}
+
+/// `key="value"`, `key='value'` and `key=value` with where the value starts in the tag
+fn attributes(tag: &str) -> Vec<(String, String, usize)> {
+    let mut attributes = Vec::new();
+    let bytes = tag.as_bytes();
+    let mut i = tag
+        .find(|c: char| c.is_ascii_whitespace())
+        .unwrap_or(tag.len());
+    while i < tag.len() {
+        while i < tag.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+            i += 1;
+        }
+        let key_start = i;
+        while i < tag.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'=' {
+            i += 1;
+        }
+        let key = tag[key_start..i].to_ascii_lowercase();
+        while i < tag.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i >= tag.len() || bytes[i] != b'=' {
+            continue;
+        }
+        i += 1;
+        while i < tag.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let (value_start, value_end) = match bytes.get(i) {
+            Some(b'"') | Some(b'\'') => {
+                let quote = bytes[i] as char;
+                let end = tag[i + 1..].find(quote).map_or(tag.len(), |e| i + 1 + e);
+                (i + 1, end)
+            }
+            _ => {
+                let end = tag[i..]
+                    .find(|c: char| c.is_ascii_whitespace())
+                    .map_or(tag.len(), |e| i + e);
+                (i, end)
+            }
+        };
+        attributes.push((key, tag[value_start..value_end].to_string(), value_start));
+        // past the closing quote or the whitespace
+        i = value_end + tag[value_end..].chars().next().map_or(0, char::len_utf8);
+    }
+    attributes
+}
+
+/// `&amp;`, `&#47;` and `&#x2F;` etc. in an attribute back to the characters they are, only
+/// the named ones that could be in a url are known and anything else is left as it is
+fn decode_entities(input: &str) -> String {
+    let mut decoded = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..]
+            .find(';')
+            .map(|end| &rest[1..=end])
+            .filter(|entity| entity.len() <= 8);
+        let c = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        });
+        match (entity, c) {
+            (Some(entity), Some(c)) => {
+                decoded.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// `%20` etc. back to the characters that are in the file name
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn is_external(url: &str) -> bool {
+    url.starts_with("//") || url.contains("://")
+}
+
+/// links that aren't to a page e.g. `mailto:` or `javascript:`
+fn is_ignored(url: &str) -> bool {
+    url.is_empty()
+        || ["mailto:", "tel:", "javascript:", "data:"]
+            .iter()
+            .any(|scheme| url.starts_with(scheme))
+}
+
+/// `a/../b` to `b` without touching the file system, None if it goes above the output
+fn normalise(path: &Path) -> Option<PathBuf> {
+    let mut normalised = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir if !normalised.pop() => return None,
+            Component::Normal(part) => normalised.push(part),
+            _ => {}
+        }
+    }
+    Some(normalised)
+}
+
+fn html_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    if let Ok(entries) = dir.read_dir() {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                html_files(&path, files);
+            } else if path.extension().is_some_and(|e| e == "html" || e == "htm") {
+                files.push(path);
+            }
+        }
+    }
+}
+
+/// what came out of checking the output
+#[derive(Debug, Default)]
+pub struct LinkReport {
+    /// a warning for each link to a file or anchor that doesn't exist
+    pub broken: Vec<CustomError>,
+    /// every external link and the pages they are on, these aren't checked
+    pub external: Vec<(String, Vec<PathBuf>)>,
+}
+
+/// checks every `href` and `src` in the html files in `output` point at a file that was built,
+/// `/` is the root of the output (after the `baseurl`) and `#anchors` have to be an `id`.
+/// Minified pages are all on one line so the broken links don't say which line they are on
+pub fn check_links(output: &Path, baseurl: &str) -> LinkReport {
+    let mut files = Vec::new();
+    html_files(output, &mut files);
+    files.sort();
+
+    let mut pages: HashMap<PathBuf, Page> = HashMap::new();
+    for file in &files {
+        if let Ok(html) = std::fs::read_to_string(file) {
+            pages.insert(file.clone(), parse_html(&html));
+        }
+    }
+
+    let baseurl = baseurl.trim_matches('/');
+    let mut report = LinkReport::default();
+    let mut external: HashMap<String, BTreeSet<PathBuf>> = HashMap::new();
+    for file in &files {
+        let page = match pages.get(file) {
+            Some(page) => page,
+            None => continue,
+        };
+        for reference in &page.references {
+            let url = decode_entities(reference.url.trim());
+            let url = url.as_str();
+            if is_ignored(url) {
+                continue;
+            }
+            if is_external(url) {
+                external
+                    .entry(url.to_string())
+                    .or_default()
+                    .insert(file.clone());
+                continue;
+            }
+
+            let (url, fragment) = url.split_once('#').unwrap_or((url, ""));
+            let url = url.split('?').next().unwrap_or("");
+            let path = percent_decode(url);
+            let target = if path.is_empty() {
+                Some(file.clone())
+            } else if let Some(absolute) = path.strip_prefix('/') {
+                let absolute = if baseurl.is_empty() {
+                    Some(absolute)
+                } else {
+                    absolute
+                        .strip_prefix(baseurl)
+                        .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+                        .map(|rest| rest.trim_start_matches('/'))
+                };
+                absolute
+                    .and_then(|absolute| normalise(Path::new(absolute)))
+                    .map(|absolute| output.join(absolute))
+            } else {
+                file.strip_prefix(output)
+                    .ok()
+                    .and_then(|file| normalise(&file.with_file_name("").join(&path)))
+                    .map(|relative| output.join(relative))
+            };
+
+            let target = target.map(|target| {
+                if target.is_dir() {
+                    target.join("index.html")
+                } else {
+                    target
+                }
+            });
+            let message = match target {
+                Some(target) if target.is_file() => match pages.get(&target) {
+                    Some(linked)
+                        if !fragment.is_empty()
+                            && !linked.ids.contains(&percent_decode(fragment)) =>
+                    {
+                        Some(format!(
+                            "no '#{}' in {}",
+                            fragment,
+                            target.strip_prefix(output).unwrap_or(&target).display()
+                        ))
+                    }
+                    _ => None,
+                },
+                _ => Some(String::from("no such file in the output")),
+            };
+
+            if let Some(message) = message {
+                let mut e = CustomError::new(
+                    ErrorKind::BrokenLink,
+                    format!("'{}' {}", reference.url, message),
+                )
+                .path(file)
+                .warning(WarningKind::BrokenLink);
+                if !page.minified {
+                    let start = reference.text.find(&reference.url).unwrap_or(0);
+                    e = e.span(Span {
+                        line: reference.line,
+                        start,
+                        end: start + reference.url.len(),
+                        text: reference.text.clone(),
+                    });
+                }
+                report.broken.push(e);
+            }
+        }
+    }
+
+    let mut external: Vec<(String, Vec<PathBuf>)> = external
+        .into_iter()
+        .map(|(url, pages)| (url, pages.into_iter().collect()))
+        .collect();
+    external.sort();
+    report.external = external;
+    report
+}
+
+#[cfg(test)]
+mod link_check_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse() {
+        let page = parse_html(
+            "<html>\n<a class=x href=\"/a.html#top\">a</a><img src='cat.png' alt=\"a > b\">\n\
+             <!-- <a href=\"commented.html\"> -->\n\
+             <script>var a = '<a href=\"script.html\">';</script>\n\
+             <h2 id=\"top\">top</h2><a name=old></a>",
+        );
+        let urls: Vec<(&str, usize)> = page
+            .references
+            .iter()
+            .map(|r| (r.url.as_str(), r.line))
+            .collect();
+        assert_eq!(vec![("/a.html#top", 2), ("cat.png", 2)], urls);
+        assert!(page.ids.contains("top"));
+        assert!(page.ids.contains("old"));
+    }
+
+    #[test]
+    fn non_ascii_attributes() {
+        // a non breaking space isn't whitespace in html so it's part of the value
+        assert_eq!(
+            vec![
+                (String::from("href"), String::from("x\u{a0}y"), 7),
+                (String::from("title"), String::from("é"), 18),
+            ],
+            attributes("a href=x\u{a0}y title=é")
+        );
+        assert_eq!(
+            vec![(String::from("href"), String::from("é"), 8)],
+            attributes("a href=\"é\"\u{a0}")
+        );
+        let page = parse_html("<a href=x\u{a0}y>a</a>");
+        assert_eq!("x\u{a0}y", page.references[0].url);
+    }
+
+    #[test]
+    fn decode() {
+        assert_eq!(
+            "cats and dogs.html",
+            percent_decode("cats%20and%20dogs.html")
+        );
+        assert_eq!("100%", percent_decode("100%"));
+        assert_eq!(
+            "a.html?x=1&y=2 /b c'd & &nope; &#xZZ;",
+            decode_entities("a.html?x=1&amp;y=2 &#47;b&#x20;c&apos;d & &nope; &#xZZ;")
+        );
+    }
+
+    #[test]
+    fn check() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path();
+        std::fs::create_dir(output.join("posts")).unwrap();
+        std::fs::write(output.join("main.css"), "").unwrap();
+        std::fs::write(
+            output.join("index.html"),
+            "<link href=\"/docs/main.css\"><a href=\"posts/\">posts</a>\n\
+             <a href=\"cats%20and%20dogs.html#top\">cats</a> <a href=\"#nope\">x</a>\n\
+             <a href=\"https://example.com\">out</a> <a href=\"mailto:a@b.c\">mail</a>",
+        )
+        .unwrap();
+        std::fs::write(
+            output.join("cats and dogs.html"),
+            "<h1 id=\"top\">cats</h1><a href=\"index.html?x=1&amp;y=2\">home</a>\
+             <a href=\"&#47;docs&#47;posts&#x2F;index.html#a&amp;b\">posts</a>",
+        )
+        .unwrap();
+        std::fs::write(
+            output.join("posts").join("index.html"),
+            "<a href=\"../missing.html\">x</a>\n<a href=\"/main.css\">x</a>\
+             <a href=\"https://example.com\">out</a><h2 id=\"a&amp;b\">a</h2>",
+        )
+        .unwrap();
+
+        let report = check_links(output, "docs/");
+        let broken: Vec<(String, usize, String)> = report
+            .broken
+            .iter()
+            .map(|e| {
+                (
+                    e.path
+                        .as_ref()
+                        .unwrap()
+                        .strip_prefix(output)
+                        .unwrap()
+                        .display()
+                        .to_string(),
+                    e.span.as_ref().unwrap().line,
+                    e.message.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    String::from("index.html"),
+                    2,
+                    String::from("'#nope' no '#nope' in index.html")
+                ),
+                (
+                    String::from("posts/index.html"),
+                    1,
+                    String::from("'../missing.html' no such file in the output")
+                ),
+                (
+                    String::from("posts/index.html"),
+                    2,
+                    String::from("'/main.css' no such file in the output")
+                ),
+            ],
+            broken
+        );
+        assert_eq!(1, report.external.len());
+        assert_eq!(2, report.external[0].1.len());
+
+        // the line would always be 1 once it's minified
+        std::fs::write(
+            output.join("posts").join("index.html"),
+            format!("<a href=\"../missing.html\">{}</a>", "x".repeat(MAX_LINE)),
+        )
+        .unwrap();
+        let report = check_links(output, "docs/");
+        assert_eq!(
+            vec![true, true, false],
+            report
+                .broken
+                .iter()
+                .map(|e| e.span.is_some())
+                .collect::<Vec<bool>>()
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use liquid_core::error::{ResultLiquidExt, ResultLiquidReplaceExt};
+use liquid_core::Error;
+use liquid_core::Language;
+use liquid_core::Renderable;
+use liquid_core::Result;
+use liquid_core::Runtime;
+use liquid_core::{ParseTag, TagReflection, TagTokenIter};
+
+use crate::url_filter::relative_url;
+
+/// where every article ends up so `{% link %}` and `{% post_url %}` can find them
+#[derive(Debug, Default)]
+pub struct Links {
+    /// the article's path from the root of the site e.g. `_articles/cats.md` and it's url
+    articles: Vec<(PathBuf, String)>,
+}
+
+impl Links {
+    pub fn new<'a>(
+        articles: impl Iterator<Item = (&'a Path, &'a str)>,
+        root: &Path,
+        baseurl: &str,
+    ) -> Self {
+        Links {
+            articles: articles
+                .map(|(path, url)| {
+                    (
+                        path.strip_prefix(root).unwrap_or(path).to_path_buf(),
+                        relative_url(baseurl, url),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// `_articles/cats.md`, the whole path from the root of the site like jekyll so two
+    /// articles with the same file name can't be mixed up
+    fn link(&self, target: &str) -> Option<&str> {
+        self.articles
+            .iter()
+            .find(|(path, _)| path == Path::new(target))
+            .map(|(_, url)| url.as_str())
+    }
+
+    /// `2020-11-13-cats` the file name without the extension
+    fn post(&self, target: &str) -> Option<&str> {
+        self.articles
+            .iter()
+            .find(|(path, _)| path.with_extension("").ends_with(target))
+            .map(|(_, url)| url.as_str())
+    }
+
+    fn paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = self
+            .articles
+            .iter()
+            .map(|(path, _)| path.to_string_lossy().replace('\\', "/"))
+            .collect();
+        paths.sort_unstable();
+        paths
+    }
+
+    fn posts(&self) -> Vec<String> {
+        let mut posts: Vec<String> = self
+            .articles
+            .iter()
+            .filter_map(|(path, _)| path.file_stem())
+            .map(|stem| stem.to_string_lossy().to_string())
+            .collect();
+        posts.sort_unstable();
+        posts
+    }
+}
+
+/// the file name, quotes are needed for paths with a `/` in them or names starting with a
+/// date as liquid won't parse them
+fn target(arguments: &mut TagTokenIter<'_>) -> Result<String> {
+    let target = arguments
+        .expect_next("file name expected.")?
+        .as_str()
+        .trim_matches(|c| c == '"' || c == '\'')
+        .to_string();
+    arguments.expect_nothing()?;
+    Ok(target)
+}
+
+/// a link, asset or image that doesn't exist, it has the same context as liquid's unknown
+/// variable errors so the closest is suggested
+pub fn not_found<S: AsRef<str>>(
+    kind: &str,
+    target: &str,
+    available: impl IntoIterator<Item = S>,
+) -> Error {
+    let available: Vec<S> = available.into_iter().collect();
+    let available: Vec<&str> = available.iter().map(AsRef::as_ref).collect();
+    Error::with_msg(format!("{} not found", kind))
+        .context(format!("requested {}", kind), target.to_string())
+        .context(format!("available {}s", kind), available.join(", "))
+}
+
+#[derive(Debug)]
+struct Link {
+    target: String,
+    links: Arc<Links>,
+}
+
+impl Renderable for Link {
+    fn render_to(&self, writer: &mut dyn Write, _runtime: &mut Runtime<'_>) -> Result<()> {
+        let url = self
+            .links
+            .link(&self.target)
+            .ok_or_else(|| not_found("link", &self.target, self.links.paths()))
+            .trace_with(|| format!("{{% link {} %}}", self.target).into())?;
+        write!(writer, "{}", url).replace("Failed to render")
+    }
+}
+
+#[derive(Debug)]
+struct PostUrl {
+    target: String,
+    links: Arc<Links>,
+}
+
+impl Renderable for PostUrl {
+    fn render_to(&self, writer: &mut dyn Write, _runtime: &mut Runtime<'_>) -> Result<()> {
+        let url = self
+            .links
+            .post(&self.target)
+            .ok_or_else(|| not_found("post", &self.target, self.links.posts()))
+            .trace_with(|| format!("{{% post_url {} %}}", self.target).into())?;
+        write!(writer, "{}", url).replace("Failed to render")
+    }
+}
+
+/// `{% link _articles/cats.md %}` is the url of that article, it's an error if there isn't one
+#[derive(Clone, Debug, Default)]
+pub struct LinkTag {
+    links: Arc<Links>,
+}
+
+impl LinkTag {
+    pub fn new(links: Arc<Links>) -> Self {
+        LinkTag { links }
+    }
+}
+
+impl TagReflection for LinkTag {
+    fn tag(&self) -> &'static str {
+        "link"
+    }
+
+    fn description(&self) -> &'static str {
+        "the url of an article from it's path"
+    }
+}
+
+impl ParseTag for LinkTag {
+    fn parse(
+        &self,
+        mut arguments: TagTokenIter<'_>,
+        _options: &Language,
+    ) -> Result<Box<dyn Renderable>> {
+        Ok(Box::new(Link {
+            target: target(&mut arguments)?,
+            links: self.links.clone(),
+        }))
+    }
+
+    fn reflection(&self) -> &dyn TagReflection {
+        self
+    }
+}
+
+/// `{% post_url '2020-11-13-cats' %}` the same as `link` but from the file name without the
+/// extension like jekyll's posts
+#[derive(Clone, Debug, Default)]
+pub struct PostUrlTag {
+    links: Arc<Links>,
+}
+
+impl PostUrlTag {
+    pub fn new(links: Arc<Links>) -> Self {
+        PostUrlTag { links }
+    }
+}
+
+impl TagReflection for PostUrlTag {
+    fn tag(&self) -> &'static str {
+        "post_url"
+    }
+
+    fn description(&self) -> &'static str {
+        "the url of an article from it's file name"
+    }
+}
+
+impl ParseTag for PostUrlTag {
+    fn parse(
+        &self,
+        mut arguments: TagTokenIter<'_>,
+        _options: &Language,
+    ) -> Result<Box<dyn Renderable>> {
+        Ok(Box::new(PostUrl {
+            target: target(&mut arguments)?,
+            links: self.links.clone(),
+        }))
+    }
+
+    fn reflection(&self) -> &dyn TagReflection {
+        self
+    }
+}
+
+#[cfg(test)]
+mod link_tag_tests {
+    use super::*;
+    use crate::util::render_with;
+    use pretty_assertions::assert_eq;
+
+    fn render(template: &str) -> Result<String> {
+        let articles = [
+            (Path::new("site/_articles/2020-11-13-cats.md"), "cats.html"),
+            (
+                Path::new("site/_articles/dogs.md"),
+                "dogs%20and%20more.html",
+            ),
+            (Path::new("site/index.md"), "index.html"),
+        ];
+        let links = Arc::new(Links::new(
+            articles.iter().copied(),
+            Path::new("site"),
+            "/blog",
+        ));
+        render_with(
+            |builder| {
+                builder
+                    .tag(LinkTag::new(links.clone()))
+                    .tag(PostUrlTag::new(links))
+            },
+            template,
+            &liquid::object!({}),
+        )
+    }
+
+    #[test]
+    fn link() {
+        assert_eq!(
+            "/blog/dogs%20and%20more.html /blog/index.html",
+            render("{% link '_articles/dogs.md' %} {% link index.md %}").unwrap()
+        );
+    }
+
+    #[test]
+    fn post_url() {
+        assert_eq!(
+            "/blog/cats.html",
+            render("{% post_url '2020-11-13-cats' %}").unwrap()
+        );
+    }
+
+    #[test]
+    fn not_found() {
+        let e = render("{% link '_articles/dog.md' %}")
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("requested link=_articles/dog.md"), "{}", e);
+        assert!(
+            e.contains("available links=_articles/2020-11-13-cats.md, _articles/dogs.md, index.md"),
+            "{}",
+            e
+        );
+        // the end of the path isn't enough, it could be more than one article
+        let e = render("{% link 'dogs.md' %}").unwrap_err().to_string();
+        assert!(e.contains("requested link=dogs.md"), "{}", e);
+        let e = render("{% post_url '2020-11-13-cat' %}")
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("requested post=2020-11-13-cat"), "{}", e);
+    }
+}
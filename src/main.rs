@@ -1,4 +1,5 @@
 use argh::FromArgs;
+use std::process::ExitCode;
 
 pub mod cmds;
 
@@ -9,7 +10,7 @@ struct TopLevel {
     nested: cmds::SubCommands,
 }
 
-fn main() {
+fn main() -> ExitCode {
     simple_logger::init().unwrap();
-    argh::from_env::<TopLevel>().nested.run();
+    argh::from_env::<TopLevel>().nested.run()
 }
@@ -0,0 +1,359 @@
+use pulldown_cmark::{html, CodeBlockKind, Event, Options, Parser, Tag};
+
+use crate::config::{MarkdownConfig, SiteConfig};
+use crate::error::CustomError;
+use crate::highlight::Highlighter;
+use crate::protect::{protect_liquid, restore_liquid, unwrap_liquid_tags};
+use crate::toc::{headings, toc_html, TocEntry};
+
+/// markdown to html, shared by every article so that the highlighter only has to be loaded once
+#[derive(Default)]
+pub struct Markdown {
+    config: MarkdownConfig,
+    highlighter: Option<Highlighter>,
+}
+
+/// per article overrides of the site wide `[markdown]` config, set in the front matter
+#[derive(Debug, Default, PartialEq)]
+pub struct MarkdownOverrides {
+    pub tables: Option<bool>,
+    pub footnotes: Option<bool>,
+    pub strikethrough: Option<bool>,
+    pub tasklists: Option<bool>,
+    pub smart_punctuation: Option<bool>,
+    pub heading_ids: Option<bool>,
+    pub heading_anchors: Option<bool>,
+    pub toc_depth: Option<u32>,
+    pub liquid_first: Option<bool>,
+}
+
+pub struct Rendered {
+    pub html: String,
+    pub toc: Vec<TocEntry>,
+}
+
+impl Rendered {
+    /// `page.toc`, both the nested headings and the ready made html list
+    pub fn toc_to_liquid(&self) -> liquid::model::Value {
+        liquid::model::Value::Object(liquid::object!({
+            "items": liquid::model::to_value(&self.toc).unwrap_or(liquid::model::Value::Nil),
+            "html": toc_html(&self.toc),
+        }))
+    }
+}
+
+impl MarkdownOverrides {
+    /// keys that aren't markdown extensions are ignored
+    pub fn set(&mut self, key: &str, value: bool) {
+        let option = match key {
+            "tables" => &mut self.tables,
+            "footnotes" => &mut self.footnotes,
+            "strikethrough" => &mut self.strikethrough,
+            "tasklists" => &mut self.tasklists,
+            "smart_punctuation" => &mut self.smart_punctuation,
+            "heading_ids" => &mut self.heading_ids,
+            "heading_anchors" => &mut self.heading_anchors,
+            "liquid_first" => &mut self.liquid_first,
+            _ => return,
+        };
+        *option = Some(value);
+    }
+}
+
+impl Markdown {
+    pub fn new(config: &SiteConfig) -> Result<Self, CustomError> {
+        Ok(Markdown {
+            config: config.markdown.clone(),
+            highlighter: if config.highlight.enabled {
+                Some(Highlighter::new(&config.highlight)?)
+            } else {
+                None
+            },
+        })
+    }
+
+    /// whether liquid should be rendered before the markdown, otherwise the liquid is
+    /// protected from the markdown and rendered afterwards
+    pub fn liquid_first(&self, overrides: &MarkdownOverrides) -> bool {
+        overrides.liquid_first.unwrap_or(self.config.liquid_first)
+    }
+
+    pub fn render(&self, template: &str, overrides: &MarkdownOverrides) -> Rendered {
+        let (template, protected) = protect_liquid(template);
+        let parser = Parser::new_ext(&template, self.options(overrides));
+
+        let events = match &self.highlighter {
+            Some(highlighter) => highlight(parser, highlighter),
+            None => parser.collect(),
+        };
+
+        let mut toc = Vec::new();
+        let events = if overrides.heading_ids.unwrap_or(self.config.heading_ids) {
+            headings(
+                events.into_iter(),
+                overrides
+                    .heading_anchors
+                    .unwrap_or(self.config.heading_anchors),
+                overrides.toc_depth.unwrap_or(self.config.toc_depth),
+                &protected,
+                &mut toc,
+            )
+        } else {
+            events
+        };
+
+        // Write to String buffer.
+        let mut output = String::new();
+        html::push_html(
+            &mut output,
+            unwrap_liquid_tags(events, &protected).into_iter(),
+        );
+        Rendered {
+            html: restore_liquid(&output, &protected),
+            toc,
+        }
+    }
+
+    fn options(&self, overrides: &MarkdownOverrides) -> Options {
+        let mut options = Options::empty();
+        for (enabled, overridden, option) in &[
+            (self.config.tables, overrides.tables, Options::ENABLE_TABLES),
+            (
+                self.config.footnotes,
+                overrides.footnotes,
+                Options::ENABLE_FOOTNOTES,
+            ),
+            (
+                self.config.strikethrough,
+                overrides.strikethrough,
+                Options::ENABLE_STRIKETHROUGH,
+            ),
+            (
+                self.config.tasklists,
+                overrides.tasklists,
+                Options::ENABLE_TASKLISTS,
+            ),
+            (
+                self.config.smart_punctuation,
+                overrides.smart_punctuation,
+                Options::ENABLE_SMART_PUNCTUATION,
+            ),
+        ] {
+            if overridden.unwrap_or(*enabled) {
+                options.insert(*option);
+            }
+        }
+        options
+    }
+
+    /// css for the highlighting theme when it's using css classes instead of inline styles
+    pub fn stylesheet(&self) -> Option<String> {
+        self.highlighter.as_ref()?.stylesheet()
+    }
+}
+
+/// swaps fenced code blocks with a language for highlighted html,
+/// if the language isn't known then the events are left as they are
+fn highlight<'a>(parser: Parser<'a>, highlighter: &Highlighter) -> Vec<Event<'a>> {
+    let mut events = Vec::new();
+    let mut code_block: Option<Vec<Event<'a>>> = None;
+
+    for event in parser {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(ref info))) if !info.is_empty() => {
+                code_block = Some(vec![event]);
+            }
+            Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(ref info))) if code_block.is_some() => {
+                let mut block = code_block.take().unwrap();
+                let lang = info.split([' ', ',']).next().unwrap_or("");
+                let code: String = block
+                    .iter()
+                    .filter_map(|e| match e {
+                        Event::Text(text) => Some(text.as_ref()),
+                        _ => None,
+                    })
+                    .collect();
+
+                match highlighter.highlight(&code, lang) {
+                    Some(html) => events.push(Event::Html(html.into())),
+                    None => {
+                        block.push(event);
+                        events.append(&mut block);
+                    }
+                }
+            }
+            event => match code_block.as_mut() {
+                Some(block) => block.push(event),
+                None => events.push(event),
+            },
+        }
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod markdown_tests {
+    use super::*;
+    use crate::config::HighlightConfig;
+    use pretty_assertions::assert_eq;
+
+    fn markdown(highlight: HighlightConfig) -> Markdown {
+        Markdown::new(&SiteConfig {
+            highlight,
+            ..SiteConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn no_highlighting() {
+        let md = markdown(HighlightConfig {
+            enabled: false,
+            ..HighlightConfig::default()
+        });
+        assert_eq!(
+            "<pre><code class=\"language-rust\">let a = 1;\n</code></pre>\n",
+            md.render("```rust\nlet a = 1;\n```", &MarkdownOverrides::default())
+                .html
+        );
+    }
+
+    #[test]
+    fn unknown_language() {
+        let md = markdown(HighlightConfig::default());
+        assert_eq!(
+            "<pre><code class=\"language-nope\">let a = 1;\n</code></pre>\n",
+            md.render("```nope\nlet a = 1;\n```", &MarkdownOverrides::default())
+                .html
+        );
+    }
+
+    #[test]
+    fn inline_styles() {
+        let md = markdown(HighlightConfig::default());
+        let html = md
+            .render("```rust\nlet a = 1;\n```", &MarkdownOverrides::default())
+            .html;
+        assert!(
+            html.starts_with("<pre style=\"background-color:#"),
+            "{}",
+            html
+        );
+        assert!(html.contains("<code class=\"language-rust\">"), "{}", html);
+        assert!(html.contains("<span style=\"color:"), "{}", html);
+        assert!(md.stylesheet().is_none());
+    }
+
+    #[test]
+    fn css_classes_with_line_numbers() {
+        let md = markdown(HighlightConfig {
+            css_classes: true,
+            line_numbers: true,
+            ..HighlightConfig::default()
+        });
+        let html = md
+            .render(
+                "```rust\nlet a = 1;\nlet b = 2;\n```",
+                &MarkdownOverrides::default(),
+            )
+            .html;
+        assert!(html.starts_with("<pre class=\"hl-code\">"), "{}", html);
+        assert!(
+            html.contains("<span class=\"hl-storage hl-type hl-rust\">let</span>"),
+            "{}",
+            html
+        );
+        assert!(
+            html.contains("<span class=\"hl-lineno\">2</span>"),
+            "{}",
+            html
+        );
+        assert!(md.stylesheet().unwrap().contains(".hl-lineno"));
+    }
+
+    #[test]
+    fn unknown_theme() {
+        assert!(Markdown::new(&SiteConfig {
+            highlight: HighlightConfig {
+                theme: "nope".into(),
+                ..HighlightConfig::default()
+            },
+            ..SiteConfig::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn toc() {
+        let md = markdown(HighlightConfig::default());
+        let mut overrides = MarkdownOverrides::default();
+        let rendered = md.render("# a\n## b\n### c", &overrides);
+        assert_eq!(
+            "<h1 id=\"a\">a</h1>\n<h2 id=\"b\">b</h2>\n<h3 id=\"c\">c</h3>\n",
+            rendered.html
+        );
+        assert_eq!(1, rendered.toc.len());
+        assert_eq!(1, rendered.toc[0].children[0].children.len());
+
+        overrides.toc_depth = Some(2);
+        let rendered = md.render("# a\n## b\n### c", &overrides);
+        assert!(rendered.toc[0].children[0].children.is_empty());
+
+        overrides.set("heading_ids", false);
+        let rendered = md.render("# a\n## b\n### c", &overrides);
+        assert_eq!("<h1>a</h1>\n<h2>b</h2>\n<h3>c</h3>\n", rendered.html);
+        assert!(rendered.toc.is_empty());
+    }
+
+    #[test]
+    fn protected_liquid() {
+        let md = markdown(HighlightConfig {
+            enabled: false,
+            ..HighlightConfig::default()
+        });
+        let overrides = MarkdownOverrides::default();
+        assert_eq!(
+            "{% include 'image' alt=\"*cat*\" %}\n<p><em>{{ page.title }}</em> “dog”</p>\n<p><a href=\"{{ art.url }}\">{{ art.title }}</a></p>\n<pre><code class=\"language-html\">&lt;a href=&quot;{{ url }}&quot;&gt;\n</code></pre>\n",
+            md.render(
+                "{% include 'image' alt=\"*cat*\" %}\n\n*{{ page.title }}* \"dog\"\n\n[{{ art.title }}]({{ art.url }})\n\n```html\n<a href=\"{{ url }}\">\n```",
+                &overrides
+            )
+            .html
+        );
+    }
+
+    #[test]
+    fn extensions() {
+        let md = markdown(HighlightConfig::default());
+        let overrides = MarkdownOverrides::default();
+        assert_eq!(
+            "<p><del>cat</del></p>\n",
+            md.render("~~cat~~", &overrides).html
+        );
+        assert_eq!(
+            "<ul>\n<li><input disabled=\"\" type=\"checkbox\" checked=\"\"/>\ncat</li>\n</ul>\n",
+            md.render("- [x] cat", &overrides).html
+        );
+        assert!(md
+            .render("| a | b |\n|---|---|\n| 1 | 2 |", &overrides)
+            .html
+            .starts_with("<table>"));
+        assert!(md
+            .render("cat[^1]\n\n[^1]: dog", &overrides)
+            .html
+            .contains("<sup class=\"footnote-reference\">"));
+    }
+
+    #[test]
+    fn extension_overrides() {
+        let md = markdown(HighlightConfig::default());
+        let mut overrides = MarkdownOverrides::default();
+        overrides.set("strikethrough", false);
+        overrides.set("smart_punctuation", true);
+        assert_eq!(
+            "<p>~~cat~~ “dog”</p>\n",
+            md.render("~~cat~~ \"dog\"", &overrides).html
+        );
+    }
+}
@@ -0,0 +1,379 @@
+use crate::json_filter;
+use crate::util::tag_end;
+
+/// the contents of these are copied across as they are (other than `<script>` and `<style>`
+/// which get their own minifying), whitespace matters in them
+const RAW: [&str; 5] = ["pre", "textarea", "code", "script", "style"];
+
+/// collapses whitespace down to a single space and removes comments, it doesn't remove
+/// whitespace between tags completely as that can change how inline elements look.
+/// Conditional comments e.g. `<!--[if IE]>` are kept
+pub fn html(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut space = false;
+    let mut i = 0;
+    while let Some(c) = input[i..].chars().next() {
+        if c.is_whitespace() {
+            space = true;
+            i += c.len_utf8();
+            continue;
+        }
+
+        if input[i..].starts_with("<!--") {
+            let end = input[i..]
+                .find("-->")
+                .map_or(input.len(), |end| i + end + 3);
+            if input[i..].starts_with("<!--[") {
+                push_space(&mut output, &mut space);
+                output.push_str(&input[i..end]);
+            }
+            i = end;
+            continue;
+        }
+
+        push_space(&mut output, &mut space);
+        let end = match c {
+            '<' => tag_end(input, i),
+            _ => None,
+        };
+        let end = match end {
+            Some(end) => end,
+            None => {
+                output.push(c);
+                i += c.len_utf8();
+                continue;
+            }
+        };
+
+        let tag = &input[i..=end];
+        output.push_str(&collapse_tag(tag));
+        i = end + 1;
+
+        let name = tag[1..]
+            .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        if !RAW.contains(&name.as_str()) || tag.ends_with("/>") {
+            continue;
+        }
+        let close = format!("</{}", name);
+        let content_end = input[i..]
+            .to_ascii_lowercase()
+            .find(&close)
+            .map_or(input.len(), |end| i + end);
+        let content = &input[i..content_end];
+        match name.as_str() {
+            "style" => output.push_str(&css(content)),
+            "script" => output.push_str(&script(tag, content)),
+            _ => output.push_str(content),
+        }
+        i = content_end;
+    }
+    output
+}
+
+fn push_space(output: &mut String, space: &mut bool) {
+    if *space && !output.is_empty() {
+        output.push(' ');
+    }
+    *space = false;
+}
+
+/// `<a\n   href="x"  >` to `<a href="x">`, the attribute values are left alone
+fn collapse_tag(tag: &str) -> String {
+    let mut output = String::with_capacity(tag.len());
+    let mut quote = None;
+    let mut space = false;
+    for c in tag.chars() {
+        match quote {
+            Some(q) => {
+                output.push(c);
+                if c == q {
+                    quote = None;
+                }
+            }
+            None if c.is_whitespace() => space = true,
+            None => {
+                if space && c != '>' && !(c == '/' && tag.ends_with("/>")) {
+                    output.push(' ');
+                }
+                space = false;
+                if c == '"' || c == '\'' {
+                    quote = Some(c);
+                }
+                output.push(c);
+            }
+        }
+    }
+    output
+}
+
+/// removes comments and the whitespace that isn't needed, strings are left alone. Spaces
+/// before a `:` are kept as `a :hover` and `a:hover` are different selectors
+pub fn css(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    let mut quote = None;
+    let mut space = false;
+    let tight = |c: char| "{};,>".contains(c);
+    while let Some(c) = chars.next() {
+        if let Some(q) = quote {
+            output.push(c);
+            if c == '\\' {
+                output.extend(chars.next());
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+
+        if c == '/' && chars.peek() == Some(&'*') {
+            chars.next();
+            let mut previous = ' ';
+            for c in chars.by_ref() {
+                if previous == '*' && c == '/' {
+                    break;
+                }
+                previous = c;
+            }
+            space = true;
+            continue;
+        }
+        if c.is_whitespace() {
+            space = true;
+            continue;
+        }
+
+        if space
+            && !output.is_empty()
+            && !tight(c)
+            && !output.ends_with(tight)
+            && !output.ends_with(':')
+        {
+            output.push(' ');
+        }
+        space = false;
+        if c == '}' && output.ends_with(';') {
+            output.pop();
+        }
+        if c == '"' || c == '\'' {
+            quote = Some(c);
+        }
+        output.push(c);
+    }
+    output
+}
+
+/// javascript has the indentation, trailing whitespace and blank lines removed, new lines are
+/// kept as semicolons are optional. Json is re-written on one line (escaped again so a
+/// `</script>` in a string can't end the tag) and anything else e.g. `type="text/template"`
+/// is left alone
+fn script(tag: &str, content: &str) -> String {
+    let kind = tag
+        .to_ascii_lowercase()
+        .split("type=")
+        .nth(1)
+        .map(|kind| {
+            kind.trim_start_matches(['"', '\''])
+                .split(|c: char| c == '"' || c == '\'' || c.is_whitespace() || c == '>')
+                .next()
+                .unwrap_or("")
+                .to_string()
+        })
+        .unwrap_or_default();
+
+    match kind.as_str() {
+        "application/json" | "application/ld+json" => {
+            match serde_json::from_str::<serde_json::Value>(content) {
+                Ok(json) => serde_json::to_string(&json)
+                    .map(|json| json_filter::escape(&json))
+                    .unwrap_or_else(|_| content.to_string()),
+                Err(_) => content.to_string(),
+            }
+        }
+        "" | "text/javascript" | "module" => javascript(content),
+        _ => content.to_string(),
+    }
+}
+
+/// where we are in the javascript, whitespace is only removed in `Code`
+#[derive(Clone, Copy, PartialEq)]
+enum Js {
+    Code,
+    /// a string or template literal, quoted with the char
+    Quote(char),
+    Regex {
+        class: bool,
+    },
+    LineComment,
+    BlockComment,
+}
+
+/// only the whitespace at the start and end of lines that is outside of strings, template
+/// literals, regexes and comments is removed so none of them change
+fn javascript(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    let mut state = Js::Code;
+    // how many `{` are open in each `${` of the template literals we're in
+    let mut templates: Vec<usize> = Vec::new();
+    let mut line_start = true;
+    // the last char of code that wasn't whitespace, to tell a regex from a division
+    let mut last = None;
+    while let Some(c) = chars.next() {
+        match state {
+            Js::Quote(q) => {
+                output.push(c);
+                if c == '\\' {
+                    output.extend(chars.next());
+                } else if c == q {
+                    state = Js::Code;
+                    last = Some(c);
+                } else if q == '`' && c == '$' && chars.peek() == Some(&'{') {
+                    output.extend(chars.next());
+                    templates.push(0);
+                    state = Js::Code;
+                    last = Some('{');
+                }
+                continue;
+            }
+            Js::Regex { class } => {
+                output.push(c);
+                match c {
+                    '\\' => output.extend(chars.next()),
+                    '[' => state = Js::Regex { class: true },
+                    ']' if class => state = Js::Regex { class: false },
+                    '/' if !class => {
+                        state = Js::Code;
+                        last = Some(c);
+                    }
+                    '\n' => state = Js::Code,
+                    _ => {}
+                }
+                continue;
+            }
+            Js::LineComment if c != '\n' => {
+                output.push(c);
+                continue;
+            }
+            Js::BlockComment => {
+                if c == '*' && chars.peek() == Some(&'/') {
+                    output.push(c);
+                    output.extend(chars.next());
+                    state = Js::Code;
+                    continue;
+                }
+            }
+            Js::LineComment => state = Js::Code,
+            Js::Code => {}
+        }
+
+        if c == '\n' {
+            let end = output.trim_end_matches([' ', '\t', '\r']).len();
+            output.truncate(end);
+            if !output.is_empty() && !output.ends_with('\n') {
+                output.push('\n');
+            }
+            line_start = true;
+            continue;
+        }
+        if c.is_whitespace() && line_start {
+            continue;
+        }
+        line_start = false;
+        output.push(c);
+        if state == Js::BlockComment || c.is_whitespace() {
+            continue;
+        }
+
+        match c {
+            '"' | '\'' | '`' => state = Js::Quote(c),
+            '/' if chars.peek() == Some(&'/') => state = Js::LineComment,
+            '/' if chars.peek() == Some(&'*') => {
+                output.extend(chars.next());
+                state = Js::BlockComment;
+            }
+            '/' if last.is_none_or(|last| "(,=:[!&|?{};+-*%<>~^".contains(last)) => {
+                state = Js::Regex { class: false }
+            }
+            '{' => {
+                if let Some(depth) = templates.last_mut() {
+                    *depth += 1;
+                }
+            }
+            '}' => match templates.last_mut() {
+                Some(0) => {
+                    templates.pop();
+                    state = Js::Quote('`');
+                }
+                Some(depth) => *depth -= 1,
+                None => {}
+            },
+            _ => {}
+        }
+        last = Some(c);
+    }
+    if state == Js::Code || state == Js::LineComment {
+        let end = output.trim_end().len();
+        output.truncate(end);
+    }
+    output
+}
+
+#[cfg(test)]
+mod minify_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn whitespace_and_comments() {
+        assert_eq!(
+            "<html> <body class=\"a  b\"> <p>hello <b>world</b></p> <!--[if IE]>old<![endif]--> </body> </html>",
+            html(
+                "<html>\n  <body   class=\"a  b\"  >\n    <!-- a comment -->\n    <p>hello\n\n   <b>world</b></p>\n    <!--[if IE]>old<![endif]-->\n  </body>\n</html>\n"
+            )
+        );
+    }
+
+    #[test]
+    fn raw() {
+        let pre = "<pre><code>fn main() {\n    println!(\"hi\");\n}\n</code></pre>";
+        assert_eq!(pre, html(pre));
+        assert_eq!(
+            "<p>a <code>x   =  1</code> <textarea>\n  b\n</textarea></p>",
+            html("<p>a   <code>x   =  1</code>\n<textarea>\n  b\n</textarea></p>")
+        );
+    }
+
+    #[test]
+    fn style() {
+        assert_eq!(
+            "<style>a :hover,p>b{color:red;content:\"a  /* b */\"}</style>",
+            html("<style>\n  /* links */\n  a :hover, p > b {\n    color:red;\n    content: \"a  /* b */\";\n  }\n</style>")
+        );
+    }
+
+    #[test]
+    fn scripts() {
+        assert_eq!(
+            "<script>let a = 1\nlet b = 2</script> <script type=\"application/ld+json\">{\"a\":[1,2]}</script>",
+            html("<script>\n    let a = 1\n\n    let b = 2\n</script>\n<script type=\"application/ld+json\">\n{ \"a\": [1, 2] }\n</script>")
+        );
+        // a `</script>` in a json string stays escaped
+        assert_eq!(
+            "<script type=\"application/ld+json\">{\"name\":\"\\u003c/script\\u003e\\u003cb\\u003e\"}</script>",
+            html("<script type=\"application/ld+json\">\n{ \"name\": \"\\u003c/script\\u003e\\u003cb\\u003e\" }\n</script>")
+        );
+        let template = "<script type=\"text/template\">\n  <p>{{ a }}</p>\n</script>";
+        assert_eq!(template, html(template));
+    }
+
+    #[test]
+    fn javascript_strings() {
+        assert_eq!(
+            "let a = `one\n    two ${ {b: 1}.b }\n  three`\nlet c = 'four \\\n    five'\nlet d = /  ' /\n/* a\ncomment */ f(\"  \")",
+            javascript("\n  let a = `one\n    two ${ {b: 1}.b }\n  three`  \n\n  let c = 'four \\\n    five'\n  let d = /  ' /\n  /* a\n  comment */ f(\"  \")\n")
+        );
+    }
+}
@@ -1,124 +1,99 @@
-use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
-use std::path::PathBuf;
+use chrono::{FixedOffset, NaiveDate, NaiveDateTime, NaiveTime};
+use std::path::Path;
 
-type ErrorMessage = String;
+use crate::error::{CustomError, ErrorKind, Span};
 
-#[derive(PartialEq)]
-pub enum ParseError {
-    InvalidKey(ErrorMessage),
-    EmptyValue(ErrorMessage),
-    InvalidValue(ErrorMessage),
-    InvalidConfig(ErrorMessage),
-}
-
-impl std::fmt::Debug for ParseError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            ParseError::InvalidKey(s) => writeln!(f, "Invalid key: {}", s),
-            ParseError::EmptyValue(s) => writeln!(f, "Empty value\n{}", s),
-            ParseError::InvalidValue(s) => writeln!(f, "Invalid value: {}", s),
-            ParseError::InvalidConfig(s) => writeln!(f, "Invalid configuration: {}", s),
-        }
-    }
-}
-
-pub fn parse_error_message(
+/// front matter errors, points at the line and columns that caused it
+pub fn parse_error(
+    kind: ErrorKind,
     message: &str,
-    path: &PathBuf,
+    path: &Path,
     line: &str,
     start: usize,
     end: usize,
-    lineno: i8,
-) -> ErrorMessage {
-    let spacing = if lineno < 99 {
-        "  "
-    } else if lineno < 127 {
-        "   "
-    } else {
-        "    "
-    };
-
-    let mut underline = String::new();
-    for _i in 0..start {
-        underline.push(' ');
-    }
+    lineno: usize,
+) -> CustomError {
+    CustomError::new(kind, message).path(path).span(Span {
+        line: lineno,
+        start,
+        end,
+        text: line.to_string(),
+    })
+}
 
-    for _i in start..end {
-        underline.push('^');
+/// where the value is on a front matter line, for when it's found to be wrong after parsing
+/// e.g. a layout that doesn't exist
+pub fn value_span(line: &str, value: &str, lineno: usize) -> Span {
+    let end = line.trim_end().len();
+    Span {
+        line: lineno,
+        start: line[..end].rfind(value).unwrap_or(0),
+        end,
+        text: line.to_string(),
     }
-
-    let msg : ErrorMessage = format!(
-        "\n{s   } --> {p} {n}:{start}\n{s   } |\n{n:w$} | {line}\n{s   } | {underline}\n{s   } |\n{s  }{m}",
-        p = path.to_str().unwrap(),
-        line = line,
-        s = spacing,
-        w = spacing.len(),
-        underline = underline,
-        n = lineno,
-        start = start,
-        m = message
-    );
-
-    msg
 }
 
 pub fn parse_key<'a>(
     rest: &'a str,
-    path: &PathBuf,
+    path: &Path,
     line: &str,
-    lineno: i8,
-) -> Result<(&'a str, &'a str), ParseError> {
+    lineno: usize,
+) -> Result<(&'a str, &'a str), CustomError> {
     if rest.is_empty() {
-        return Err(ParseError::EmptyValue(parse_error_message(
+        return Err(parse_error(
+            ErrorKind::EmptyValue,
             "expected name of key",
             path,
             line,
             line.len(),
             line.len() + 5,
             lineno,
-        )));
+        ));
     }
     if let Some(index) = rest.find(':') {
         return Ok((&rest[0..index], &rest[index + 1..]));
     }
-    Err(ParseError::InvalidKey(parse_error_message(
+    Err(parse_error(
+        ErrorKind::InvalidKey,
         "no semicolon found",
         path,
         line,
         line.len(),
         line.len() + 1,
         lineno,
-    )))
+    ))
 }
 
 pub fn parse_value_string<'a>(
     rest: &'a str,
-    path: &PathBuf,
+    path: &Path,
     line: &str,
-    lineno: i8,
-) -> Result<&'a str, ParseError> {
+    lineno: usize,
+) -> Result<&'a str, CustomError> {
     let rest = rest.trim();
     if rest.is_empty() {
-        return Err(ParseError::EmptyValue(parse_error_message(
+        return Err(parse_error(
+            ErrorKind::EmptyValue,
             "empty value",
             path,
             line,
             line.len(),
             line.len() + 5,
             lineno,
-        )));
+        ));
     }
 
     if rest.starts_with('"') {
         if !rest.ends_with('"') {
-            return Err(ParseError::InvalidValue(parse_error_message(
+            return Err(parse_error(
+                ErrorKind::InvalidValue,
                 "string started with \" character but did not close string at the end",
                 path,
                 line,
                 0,
                 line.len(),
                 lineno,
-            )));
+            ));
         } else {
             return Ok(&rest[1..rest.len() - 1]);
         }
@@ -126,89 +101,146 @@ pub fn parse_value_string<'a>(
 
     if rest.starts_with('\'') {
         if !rest.ends_with('\'') {
-            return Err(ParseError::InvalidValue(parse_error_message(
+            return Err(parse_error(
+                ErrorKind::InvalidValue,
                 "string started with \" character but did not close string at the end",
                 path,
                 line,
                 0,
                 line.len(),
                 lineno,
-            )));
+            ));
         } else {
             return Ok(&rest[1..rest.len() - 1]);
         }
     }
 
     if rest == "---" {
-        return Err(ParseError::InvalidValue(parse_error_message(
+        return Err(parse_error(
+            ErrorKind::InvalidValue,
             "found '---' can't use configuration start and end identifier as a value",
             path,
             line,
             line.len() - 3,
             line.len(),
             lineno,
-        )));
+        ));
     }
     Ok(rest)
 }
 
 pub fn parse_value_boolean(
     rest: &str,
-    path: &PathBuf,
+    path: &Path,
     line: &str,
-    lineno: i8,
-) -> Result<bool, ParseError> {
+    lineno: usize,
+) -> Result<bool, CustomError> {
     match rest.parse::<bool>() {
         Ok(b) => Ok(b),
-        Err(_) => Err(ParseError::InvalidValue(parse_error_message(
+        Err(_) => Err(parse_error(
+            ErrorKind::InvalidValue,
             "",
             path,
             line,
             line.len() - rest.len(),
             line.len(),
             lineno,
-        ))),
+        )),
+    }
+}
+
+pub fn parse_value_number(
+    rest: &str,
+    path: &Path,
+    line: &str,
+    lineno: usize,
+) -> Result<u32, CustomError> {
+    match rest.parse::<u32>() {
+        Ok(n) => Ok(n),
+        Err(err) => Err(parse_error(
+            ErrorKind::InvalidValue,
+            &("expected a positive number: ".to_owned() + &err.to_string()),
+            path,
+            line,
+            line.len() - rest.len(),
+            line.len(),
+            lineno,
+        )),
     }
 }
 
+/// `+01:00`, `-0500`, `Z` or `UTC`
+pub fn parse_offset(offset: &str) -> Option<FixedOffset> {
+    if offset == "Z" || offset == "UTC" {
+        return FixedOffset::east_opt(0);
+    }
+    let sign = match offset.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits = offset[1..].replacen(':', "", 1);
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// `Y-m-d`, `Y-m-d h:m` or `Y-m-d h:m:s` with an optional offset on the end e.g. `+01:00`,
+/// without one the date is in the site's timezone
 pub fn parse_value_time(
     rest: &str,
-    path: &PathBuf,
+    path: &Path,
     line: &str,
-    lineno: i8,
-) -> Result<NaiveDateTime, ParseError> {
-    match NaiveDate::parse_from_str(rest, "%Y-%m-%d") {
-        Ok(date) => Ok(date.and_time(NaiveTime::from_hms_milli(0, 0, 0, 0))),
-        Err(_) => match NaiveDateTime::parse_from_str(rest, "%Y-%m-%d %H:%M") {
-            Ok(date) => Ok(date),
-            Err(err) => Err(ParseError::InvalidValue(parse_error_message(
-                &("date error: ".to_owned() + &err.to_string() + " expected Y-m-d or Y-m-d h:m"),
-                path,
-                line,
-                line.len() - rest.len(),
-                line.len(),
-                lineno,
-            ))),
-        },
+    lineno: usize,
+) -> Result<(NaiveDateTime, Option<FixedOffset>), CustomError> {
+    let (date, offset) = match rest.rsplit_once(' ') {
+        Some((date, offset)) if parse_offset(offset).is_some() => (date, parse_offset(offset)),
+        _ => (rest, None),
+    };
+
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|date| date.and_time(NaiveTime::from_hms_milli(0, 0, 0, 0)))
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S"))
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M"));
+    match date {
+        Ok(date) => Ok((date, offset)),
+        Err(err) => Err(parse_error(
+            ErrorKind::InvalidValue,
+            &("date error: ".to_owned()
+                + &err.to_string()
+                + " expected Y-m-d, Y-m-d h:m or Y-m-d h:m:s"),
+            path,
+            line,
+            line.len() - rest.len(),
+            line.len(),
+            lineno,
+        )),
     }
 }
 
 pub fn parse_value_list(
     mut rest: &str,
-    path: &PathBuf,
+    path: &Path,
     line: &str,
-    lineno: i8,
-) -> Result<Vec<String>, ParseError> {
+    lineno: usize,
+) -> Result<Vec<String>, CustomError> {
     rest = rest.trim();
     if rest.is_empty() {
-        return Err(ParseError::EmptyValue(parse_error_message(
+        return Err(parse_error(
+            ErrorKind::EmptyValue,
             "empty",
             path,
             line,
             line.len(),
             line.len() + 5,
             lineno,
-        )));
+        ));
     }
     let mut list: Vec<String> = Vec::new();
     let mut prev = 0;
@@ -219,14 +251,15 @@ pub fn parse_value_list(
         if rest.ends_with(']') {
             rest = rest.trim_start_matches('[').trim_end_matches(']');
         } else {
-            return Err(ParseError::InvalidValue(parse_error_message(
+            return Err(parse_error(
+                ErrorKind::InvalidValue,
                 "found opening square bracket for list but no opening bracket",
                 path,
                 line,
                 0,
                 line.len(),
                 lineno,
-            )));
+            ));
         }
     }
 
@@ -243,32 +276,35 @@ pub fn parse_value_list(
         }
     }
     if prev == rest.len() {
-        return Err(ParseError::InvalidValue(parse_error_message(
+        return Err(parse_error(
+            ErrorKind::InvalidValue,
             "value expected after semi-colon",
             path,
             line,
             line.len(),
             line.len() + 5,
             lineno,
-        )));
+        ));
     } else if in_string {
-        return Err(ParseError::InvalidValue(parse_error_message(
+        return Err(parse_error(
+            ErrorKind::InvalidValue,
             "found a string but no closing \"",
             path,
             line,
             line.len() - 1,
             line.len(),
             lineno,
-        )));
+        ));
     } else if in_string_lower {
-        return Err(ParseError::InvalidValue(parse_error_message(
+        return Err(parse_error(
+            ErrorKind::InvalidValue,
             "found a string but no closing \'",
             path,
             line,
             line.len() - 1,
             line.len(),
             lineno,
-        )));
+        ));
     } else {
         list.push(parse_value_string(&rest[prev..], path, line, lineno)?.to_string());
     }
@@ -280,6 +316,7 @@ pub fn parse_value_list(
 mod parse_tests {
     use super::*;
     use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
 
     #[test]
     fn parse_key_test() {
@@ -294,7 +331,11 @@ mod parse_tests {
         let line = "hello  world";
         let err = parse_key(line, &PathBuf::from("test.txt"), line, 1).err();
         match err {
-            Some(ParseError::InvalidKey(config)) => assert!(
+            Some(CustomError {
+                kind: ErrorKind::InvalidKey,
+                message: config,
+                ..
+            }) => assert!(
                 config.contains("no semicolon found"),
                 "expected 'no semicolon found' in {}",
                 config
@@ -336,7 +377,11 @@ mod parse_tests {
         let line = "[a, b";
         let err = parse_value_list(line, &PathBuf::from("test.txt"), line, 1).err();
         match err {
-            Some(ParseError::InvalidValue(config)) => assert!(
+            Some(CustomError {
+                kind: ErrorKind::InvalidValue,
+                message: config,
+                ..
+            }) => assert!(
                 config.contains("found opening square bracket for list but no opening bracket"),
                 "found opening square bracket for list but no opening bracket' in {}",
                 config
@@ -364,7 +409,11 @@ mod parse_tests {
         let line = "a, b,";
         let err = parse_value_list(line, &PathBuf::from("test.txt"), line, 1).err();
         match err {
-            Some(ParseError::InvalidValue(config)) => assert!(
+            Some(CustomError {
+                kind: ErrorKind::InvalidValue,
+                message: config,
+                ..
+            }) => assert!(
                 config.contains("value expected after semi-colon"),
                 "expected 'value expected after semi-colon' in {}",
                 config
@@ -372,4 +421,30 @@ mod parse_tests {
             _ => assert!(false, "expected error"),
         }
     }
+
+    #[test]
+    fn parse_value_time_formats() {
+        let path = PathBuf::from("test.txt");
+        let (date, offset) = parse_value_time("2020-01-03", &path, "", 1).unwrap();
+        assert_eq!("2020-01-03 00:00:00", date.to_string());
+        assert!(offset.is_none());
+        let (date, _) = parse_value_time("2020-01-03 10:30", &path, "", 1).unwrap();
+        assert_eq!("2020-01-03 10:30:00", date.to_string());
+        let (date, offset) = parse_value_time("2020-01-03 10:30:15 +01:00", &path, "", 1).unwrap();
+        assert_eq!("2020-01-03 10:30:15", date.to_string());
+        assert_eq!(FixedOffset::east_opt(3600), offset);
+        assert!(parse_value_time("03/01/2020", &path, "date: 03/01/2020", 1).is_err());
+    }
+
+    #[test]
+    fn parse_offset_test() {
+        assert_eq!(FixedOffset::east_opt(0), parse_offset("UTC"));
+        assert_eq!(FixedOffset::east_opt(-5 * 3600), parse_offset("-0500"));
+        assert_eq!(
+            FixedOffset::east_opt(5 * 3600 + 1800),
+            parse_offset("+05:30")
+        );
+        assert_eq!(None, parse_offset("Europe/London"));
+        assert_eq!(None, parse_offset("+5"));
+    }
 }
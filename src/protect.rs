@@ -0,0 +1,218 @@
+use pulldown_cmark::{Event, Tag};
+
+const PLACEHOLDER_START: u32 = 0xF0000;
+const PLACEHOLDER_END: u32 = 0xFFFFD;
+
+fn placeholder(index: usize) -> Option<char> {
+    let code = PLACEHOLDER_START + index as u32;
+    if code <= PLACEHOLDER_END {
+        char::from_u32(code)
+    } else {
+        None
+    }
+}
+
+fn placeholder_index(c: char) -> Option<usize> {
+    let code = c as u32;
+    if (PLACEHOLDER_START..=PLACEHOLDER_END).contains(&code) {
+        Some((code - PLACEHOLDER_START) as usize)
+    } else {
+        None
+    }
+}
+
+/// keeps liquid safe from markdown, each `{{ }}` and `{% %}` is swapped for a single character from
+/// the private use area. As it's a single character it can't be split up by emphasis or the syntax
+/// highlighter and markdown has no reason to escape it, so quotes in liquid tags stay as they are.
+/// Anything that would be taken for a placeholder is swapped out too so it comes back as it was.
+/// returns the template with the liquid swapped out and the liquid that was swapped out
+pub fn protect_liquid(template: &str) -> (String, Vec<String>) {
+    let mut output = String::with_capacity(template.len());
+    let mut protected = Vec::new();
+    let mut rest = template;
+
+    while let Some((start, end)) = next_protected(rest) {
+        match placeholder(protected.len()) {
+            Some(c) => {
+                output.push_str(&rest[..start]);
+                output.push(c);
+                protected.push(rest[start..end].to_string());
+                rest = &rest[end..];
+            }
+            None => break,
+        }
+    }
+
+    output.push_str(rest);
+    (output, protected)
+}
+
+/// the start and end of the next liquid tag, or of a character from the private use area or
+/// it's percent encoding that is already in the text
+fn next_protected(text: &str) -> Option<(usize, usize)> {
+    for (start, c) in text.char_indices() {
+        let end = match c {
+            '{' => {
+                let close = match text[start..].get(..2) {
+                    Some("{{") => "}}",
+                    Some("{%") => "%}",
+                    _ => continue,
+                };
+                match text[start + 2..].find(close) {
+                    Some(end) => start + 2 + end + close.len(),
+                    None => continue,
+                }
+            }
+            '%' if percent_encoded(&text[start..]).is_some() => start + 12,
+            c if placeholder_index(c).is_some() => start + c.len_utf8(),
+            _ => continue,
+        };
+        return Some((start, end));
+    }
+    None
+}
+
+/// puts the liquid back, this includes placeholders that have been percent encoded in links
+/// e.g. `[title]({{ page.url }})`
+pub fn restore_liquid(html: &str, protected: &[String]) -> String {
+    if protected.is_empty() {
+        return html.to_string();
+    }
+
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(c) = rest.chars().next() {
+        if let Some(liquid) = placeholder_index(c).and_then(|i| protected.get(i)) {
+            output.push_str(liquid);
+            rest = &rest[c.len_utf8()..];
+        } else if let Some(liquid) = percent_encoded(rest).and_then(|i| protected.get(i)) {
+            output.push_str(liquid);
+            rest = &rest[12..];
+        } else {
+            output.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    output
+}
+
+/// placeholders are 4 bytes in utf-8 so they look like `%F3%B0%80%80` once encoded, this is
+/// the index of the placeholder at the start of `text`
+fn percent_encoded(text: &str) -> Option<usize> {
+    if !text.starts_with('%') {
+        return None;
+    }
+
+    let mut bytes = [0u8; 4];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        let part = text.get(i * 3..i * 3 + 3)?;
+        if !part.starts_with('%') {
+            return None;
+        }
+        *byte = u8::from_str_radix(&part[1..], 16).ok()?;
+    }
+
+    let c = std::str::from_utf8(&bytes).ok()?.chars().next()?;
+    placeholder_index(c)
+}
+
+/// a paragraph that is only liquid tags e.g. `{% include foo.html %}` on it's own line
+/// shouldn't be wrapped in `<p>` so the paragraph is swapped for the raw text
+pub fn unwrap_liquid_tags<'a>(events: Vec<Event<'a>>, protected: &[String]) -> Vec<Event<'a>> {
+    if protected.is_empty() {
+        return events;
+    }
+
+    let mut output = Vec::with_capacity(events.len());
+    let mut paragraph: Option<Vec<Event<'a>>> = None;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Paragraph) => paragraph = Some(Vec::new()),
+            Event::End(Tag::Paragraph) if paragraph.is_some() => {
+                let inner = paragraph.take().unwrap();
+                if only_liquid_tags(&inner, protected) {
+                    let mut text = String::new();
+                    for e in &inner {
+                        match e {
+                            Event::Text(t) => text.push_str(t),
+                            _ => text.push('\n'),
+                        }
+                    }
+                    text.push('\n');
+                    output.push(Event::Html(text.into()));
+                } else {
+                    output.push(Event::Start(Tag::Paragraph));
+                    output.extend(inner);
+                    output.push(event);
+                }
+            }
+            event => match paragraph.as_mut() {
+                Some(inner) => inner.push(event),
+                None => output.push(event),
+            },
+        }
+    }
+
+    output
+}
+
+fn only_liquid_tags(events: &[Event], protected: &[String]) -> bool {
+    let mut found = false;
+    for event in events {
+        match event {
+            Event::Text(text) => {
+                for c in text.chars() {
+                    match placeholder_index(c).and_then(|i| protected.get(i)) {
+                        Some(liquid) if liquid.starts_with("{%") => found = true,
+                        Some(_) => return false,
+                        None if c.is_whitespace() => {}
+                        None => return false,
+                    }
+                }
+            }
+            Event::SoftBreak => {}
+            _ => return false,
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod protect_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn round_trip() {
+        let template = "a {{ b }} {% c \"d\" %} {e} { {% f";
+        let (protected_template, protected) = protect_liquid(template);
+        assert_eq!(2, protected.len());
+        assert_eq!("{% c \"d\" %}", protected[1]);
+        assert!(!protected_template.contains("{{"));
+        assert_eq!(template, restore_liquid(&protected_template, &protected));
+    }
+
+    #[test]
+    fn restore_percent_encoded() {
+        let (_, protected) = protect_liquid("{{ page.url }}");
+        assert_eq!(
+            "<a href=\"{{ page.url }}\">",
+            restore_liquid("<a href=\"%F3%B0%80%80\">", &protected)
+        );
+    }
+
+    #[test]
+    fn placeholders_in_the_text() {
+        let template = "\u{F0000} [a](/%F3%B0%80%80) {{ b }} \u{F0001}";
+        let (protected_template, protected) = protect_liquid(template);
+        assert_eq!(4, protected.len());
+        assert_eq!("{{ b }}", protected[2]);
+        assert_eq!(template, restore_liquid(&protected_template, &protected));
+        // the link's placeholder is encoded by the markdown and still comes back as it was
+        assert_eq!(
+            "<a href=\"/%F3%B0%80%80\">a</a>",
+            restore_liquid("<a href=\"/%F3%B0%80%81\">a</a>", &protected)
+        );
+    }
+}
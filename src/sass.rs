@@ -0,0 +1,324 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::config::{SassConfig, SassStyle};
+use crate::error::{CustomError, ErrorKind, Span};
+
+/// compiles a `.scss` or (indented) `.sass` file to css in the configured style. Imports are
+/// looked for next to the file and then in each of the load paths, they can be either syntax
+pub fn compile(
+    path: &Path,
+    load_paths: &[&Path],
+    config: &SassConfig,
+) -> Result<String, CustomError> {
+    if !path.is_file() {
+        return Err(CustomError::io("Could not read").path(path));
+    }
+
+    let mut paths: Vec<&Path> = path.parent().into_iter().collect();
+    paths.extend(load_paths);
+    let style = match config.style {
+        SassStyle::Expanded => grass::OutputStyle::Expanded,
+        SassStyle::Compressed => grass::OutputStyle::Compressed,
+    };
+    let options = grass::Options::default()
+        .style(style)
+        .load_paths(&paths)
+        .fs(&SassFs)
+        .quiet(true);
+    // the variables are set before the file is imported so it can be in either syntax and
+    // errors still point at the file
+    let source = format!(
+        "{}@import \"{}\";",
+        variables(&config.variables),
+        path.to_string_lossy().replace('\\', "/")
+    );
+    grass::from_string(source, &options).map_err(|e| sass_error(&e.to_string(), path))
+}
+
+/// the file system with `.sass` files' `@import base` quoted as `@import "base"` which grass
+/// needs, every other file is read as it is
+#[derive(Debug)]
+struct SassFs;
+
+impl grass::Fs for SassFs {
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        let source = std::fs::read(path)?;
+        if path.extension().is_none_or(|e| e != "sass") {
+            return Ok(source);
+        }
+        Ok(quote_imports(&String::from_utf8_lossy(&source)).into_bytes())
+    }
+
+    fn canonicalize(&self, path: &Path) -> std::io::Result<PathBuf> {
+        std::fs::canonicalize(path)
+    }
+}
+
+/// `@import base, theme` to `@import "base", "theme"`, each line stays where it was so errors
+/// still point at the right place
+fn quote_imports(source: &str) -> String {
+    source
+        .split_inclusive('\n')
+        .map(|line| {
+            let indent = line.len() - line.trim_start().len();
+            let imports = match line.trim().strip_prefix("@import ") {
+                Some(imports) => imports,
+                None => return line.to_string(),
+            };
+            let imports: Vec<String> = imports
+                .split(',')
+                .map(str::trim)
+                .map(|import| {
+                    if import.starts_with('"')
+                        || import.starts_with('\'')
+                        || import.starts_with("url(")
+                    {
+                        import.to_string()
+                    } else {
+                        format!("\"{}\"", import)
+                    }
+                })
+                .collect();
+            let end = &line[line.trim_end().len()..];
+            format!("{}@import {}{}", &line[..indent], imports.join(", "), end)
+        })
+        .collect()
+}
+
+/// `[sass.variables]` e.g. `brand = "#ff6600"` as `$brand: #ff6600;`
+fn variables(variables: &BTreeMap<String, String>) -> String {
+    variables
+        .iter()
+        .map(|(name, value)| format!("${}: {}; ", name, value))
+        .collect()
+}
+
+/// every file to compile in `dir` and the directories in it, files and directories starting
+/// with a `_` are partials that are only there to be imported
+pub fn entry_points(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if let Ok(entries) = dir.read_dir() {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('_') {
+                continue;
+            }
+            if path.is_dir() {
+                files.extend(entry_points(&path));
+            } else if path.extension().is_some_and(|e| e == "scss" || e == "sass") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// `main.scss` to `main.css`, files in a directory keep it e.g. `_css/blog/post.scss` is
+/// `blog/post.css`
+pub fn output_name(path: &Path, dir: &Path) -> PathBuf {
+    path.strip_prefix(dir)
+        .unwrap_or_else(|_| Path::new(path.file_name().unwrap_or_default()))
+        .with_extension("css")
+}
+
+/// grass's errors are only available as text e.g.
+/// ```text
+/// Error: expected ";".
+///   ╷
+/// 2 │     color: red
+///   │               ^
+///   ╵
+/// ./_css/main.scss:2:15
+/// ```
+///
+fn sass_error(error: &str, path: &Path) -> CustomError {
+    let lines: Vec<&str> = error.lines().collect();
+    let message = lines
+        .first()
+        .map(|line| line.trim_start_matches("Error: "))
+        .unwrap_or("");
+    let mut e = CustomError::new(ErrorKind::Sass, message);
+
+    // the location is the last line, `stdin` is the variables and the import of the file
+    let location = lines.last().and_then(|location| {
+        let mut parts = location.trim().trim_start_matches("./").rsplitn(3, ':');
+        let column = parts.next()?.parse::<usize>().ok()?;
+        let line = parts.next()?.parse::<usize>().ok()?;
+        match parts.next()? {
+            "stdin" => None,
+            file => Some((PathBuf::from(file), line, column)),
+        }
+    });
+    match location {
+        Some((file, line, column)) => {
+            let width = lines
+                .get(3)
+                .map_or(1, |underline| underline.matches('^').count());
+            e = e.path(&file);
+            if let Some(text) = std::fs::read_to_string(&file)
+                .ok()
+                .and_then(|source| source.lines().nth(line - 1).map(String::from))
+            {
+                e = e.span(Span {
+                    line,
+                    start: column.saturating_sub(1),
+                    end: column.saturating_sub(1) + width,
+                    text,
+                });
+            }
+            e
+        }
+        None => e.path(path),
+    }
+}
+
+#[cfg(test)]
+mod sass_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn config(style: SassStyle) -> SassConfig {
+        SassConfig {
+            style,
+            ..SassConfig::default()
+        }
+    }
+
+    #[test]
+    fn quoted_imports() {
+        assert_eq!(
+            "@import \"base\", \"theme\"\n  @import 'a', url(b.css)\r\na\n  color: red",
+            quote_imports("@import base, theme\n  @import 'a', url(b.css)\r\na\n  color: red")
+        );
+    }
+
+    #[test]
+    fn compile_styles() {
+        let dir = tempfile::tempdir().unwrap();
+        let scss = dir.path().join("main.scss");
+        std::fs::write(&scss, "a {\n  b { color: red; }\n}\n").unwrap();
+        let sass = dir.path().join("main.sass");
+        std::fs::write(&sass, "$c: blue\na\n  b\n    color: $c\n").unwrap();
+
+        assert_eq!(
+            "a b {\n  color: red;\n}\n",
+            compile(&scss, &[], &config(SassStyle::Expanded)).unwrap()
+        );
+        assert_eq!(
+            "a b{color:red}",
+            compile(&scss, &[], &config(SassStyle::Compressed)).unwrap()
+        );
+        assert_eq!(
+            "a b{color:blue}",
+            compile(&sass, &[], &config(SassStyle::Compressed)).unwrap()
+        );
+        assert_eq!(PathBuf::from("main.css"), output_name(&sass, dir.path()));
+        assert_eq!(
+            PathBuf::from("blog/scss-theme.css"),
+            output_name(Path::new("_css/blog/scss-theme.scss"), Path::new("_css"))
+        );
+    }
+
+    #[test]
+    fn partials_and_load_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let css = dir.path().join("_css");
+        let vendor = dir.path().join("vendor");
+        std::fs::create_dir_all(css.join("blog")).unwrap();
+        std::fs::create_dir_all(css.join("_partials")).unwrap();
+        std::fs::create_dir_all(&vendor).unwrap();
+        std::fs::write(css.join("_colours.scss"), "$text: $brand !default;").unwrap();
+        std::fs::write(css.join("_partials").join("skip.scss"), "").unwrap();
+        std::fs::write(vendor.join("_reset.scss"), "p { margin: 0; }").unwrap();
+        std::fs::write(
+            css.join("main.scss"),
+            "@import 'colours', 'reset';\na { color: $text; }",
+        )
+        .unwrap();
+        std::fs::write(
+            css.join("blog").join("post.sass"),
+            "@import colours\nh1\n  color: $text",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![css.join("blog").join("post.sass"), css.join("main.scss")],
+            entry_points(&css)
+        );
+
+        let mut config = config(SassStyle::Compressed);
+        config
+            .variables
+            .insert(String::from("brand"), String::from("#ff6600"));
+        assert_eq!(
+            "p{margin:0}a{color:#f60}",
+            compile(&css.join("main.scss"), &[&vendor], &config).unwrap()
+        );
+        assert_eq!(
+            "h1{color:#f60}",
+            compile(&css.join("blog").join("post.sass"), &[&css], &config).unwrap()
+        );
+    }
+
+    #[test]
+    fn sass_partials() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("_base.sass"),
+            "=big\n  font-size: 2em\nbody\n  margin: 0\n",
+        )
+        .unwrap();
+        let sass = dir.path().join("main.sass");
+        std::fs::write(&sass, "@import base\nh1\n  +big\n").unwrap();
+        let scss = dir.path().join("main.scss");
+        std::fs::write(&scss, "@import 'base';\nh1 { @include big; }\n").unwrap();
+
+        let config = config(SassStyle::Compressed);
+        assert_eq!(
+            "body{margin:0}h1{font-size:2em}",
+            compile(&sass, &[], &config).unwrap()
+        );
+        assert_eq!(
+            "body{margin:0}h1{font-size:2em}",
+            compile(&scss, &[], &config).unwrap()
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let sass = dir.path().join("main.sass");
+        std::fs::write(&sass, "a\n  color: $missing\n").unwrap();
+        let e = compile(&sass, &[], &config(SassStyle::Expanded)).unwrap_err();
+        assert_eq!("Undefined variable.", e.message);
+        assert_eq!(Some(sass.clone()), e.path);
+        let span = e.span.unwrap();
+        assert_eq!(2, span.line);
+        assert_eq!("  color: $missing", span.text);
+
+        let scss = dir.path().join("main.scss");
+        std::fs::write(&scss, "a {\n  color: red\n").unwrap();
+        let e = compile(&scss, &[], &config(SassStyle::Expanded)).unwrap_err();
+        assert_eq!(Some(scss.clone()), e.path);
+        assert!(e.is_error());
+
+        // the variables from the config don't move the column on the first line
+        std::fs::write(&scss, "a { color: $nope; }").unwrap();
+        let mut config = config(SassStyle::Expanded);
+        config
+            .variables
+            .insert(String::from("brand"), String::from("red"));
+        let e = compile(&scss, &[], &config).unwrap_err();
+        assert_eq!(11, e.span.unwrap().start);
+    }
+}
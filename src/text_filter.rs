@@ -0,0 +1,204 @@
+use liquid::ValueView;
+use liquid_core::{Display_filter, Filter, FilterReflection, ParseFilter, Runtime, Value};
+use std::sync::Arc;
+
+use crate::markdown::{Markdown, MarkdownOverrides};
+
+#[derive(Clone, FilterReflection)]
+#[filter(
+    name = "markdownify",
+    description = "Converts markdown to html using the site's markdown config",
+    parsed(MarkdownifyFilter)
+)]
+pub struct Markdownify {
+    markdown: Arc<Markdown>,
+}
+
+impl Markdownify {
+    pub fn new(markdown: Arc<Markdown>) -> Self {
+        Markdownify { markdown }
+    }
+}
+
+#[derive(Display_filter)]
+#[name = "markdownify"]
+struct MarkdownifyFilter {
+    markdown: Arc<Markdown>,
+}
+
+impl std::fmt::Debug for MarkdownifyFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MarkdownifyFilter")
+    }
+}
+
+impl ParseFilter for Markdownify {
+    fn parse(
+        &self,
+        _arguments: liquid_core::parser::FilterArguments,
+    ) -> liquid_core::Result<Box<dyn Filter>> {
+        Ok(Box::new(MarkdownifyFilter {
+            markdown: self.markdown.clone(),
+        }))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self as &dyn FilterReflection
+    }
+}
+
+impl Filter for MarkdownifyFilter {
+    fn evaluate(&self, input: &dyn ValueView, _runtime: &Runtime) -> liquid_core::Result<Value> {
+        if input.is_nil() {
+            return Ok(Value::Nil);
+        }
+        Ok(Value::scalar(
+            self.markdown
+                .render(&input.to_kstr(), &MarkdownOverrides::default())
+                .html,
+        ))
+    }
+}
+
+#[derive(Clone, ParseFilter, FilterReflection)]
+#[filter(
+    name = "smartify",
+    description = "Converts quotes, dashes and ellipses to their typographic versions",
+    parsed(SmartifyFilter)
+)]
+pub struct Smartify;
+
+#[derive(Debug, Default, Display_filter)]
+#[name = "smartify"]
+struct SmartifyFilter;
+
+impl Filter for SmartifyFilter {
+    fn evaluate(&self, input: &dyn ValueView, _runtime: &Runtime) -> liquid_core::Result<Value> {
+        Ok(Value::scalar(smartify(&input.to_kstr())))
+    }
+}
+
+/// "quotes" to “quotes”, `--` to – , `---` to — and `...` to …, html tags are left alone so
+/// quotes in attributes aren't changed
+pub fn smartify(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut in_tag = false;
+    let mut previous: Option<char> = None;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_tag {
+            in_tag = c != '>';
+            output.push(c);
+            previous = Some(c);
+            continue;
+        }
+
+        // quotes open after whitespace, opening brackets and tags, otherwise they close
+        let opening = matches!(
+            previous,
+            None | Some('(' | '[' | '{' | '>' | '-' | '—' | '–')
+        ) || previous.is_some_and(char::is_whitespace);
+        match c {
+            '<' => {
+                in_tag = true;
+                output.push(c);
+            }
+            '"' => output.push(if opening { '“' } else { '”' }),
+            '\'' => output.push(if opening { '‘' } else { '’' }),
+            '-' if chars.peek() == Some(&'-') => {
+                chars.next();
+                if chars.peek() == Some(&'-') {
+                    chars.next();
+                    output.push('—');
+                } else {
+                    output.push('–');
+                }
+            }
+            '.' if chars.clone().take(2).eq(['.', '.']) => {
+                chars.next();
+                chars.next();
+                output.push('…');
+            }
+            c => output.push(c),
+        }
+        previous = output.chars().next_back();
+    }
+    output
+}
+
+#[derive(Clone, ParseFilter, FilterReflection)]
+#[filter(
+    name = "normalize_whitespace",
+    description = "Replaces any run of whitespace with a single space",
+    parsed(NormalizeWhitespaceFilter)
+)]
+pub struct NormalizeWhitespace;
+
+#[derive(Debug, Default, Display_filter)]
+#[name = "normalize_whitespace"]
+struct NormalizeWhitespaceFilter;
+
+impl Filter for NormalizeWhitespaceFilter {
+    fn evaluate(&self, input: &dyn ValueView, _runtime: &Runtime) -> liquid_core::Result<Value> {
+        Ok(Value::scalar(
+            input
+                .to_kstr()
+                .split_whitespace()
+                .collect::<Vec<&str>>()
+                .join(" "),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod text_filter_tests {
+    use super::*;
+    use crate::util::render_with;
+    use pretty_assertions::assert_eq;
+
+    fn render(template: &str) -> String {
+        let filters = |builder: liquid::ParserBuilder| {
+            builder
+                .filter(Markdownify::new(Arc::new(Markdown::default())))
+                .filter(Smartify)
+                .filter(NormalizeWhitespace)
+        };
+        render_with(
+            filters,
+            template,
+            &liquid::object!({
+                "page": { "description": "a [link](/cats.html) and *emphasis*" }
+            }),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn markdownify() {
+        assert_eq!(
+            "<p>a <a href=\"/cats.html\">link</a> and <em>emphasis</em></p>\n",
+            render("{{ page.description | markdownify }}")
+        );
+    }
+
+    #[test]
+    fn smart_punctuation() {
+        assert_eq!(
+            "“cats” aren’t dogs — or birds… ‘mostly’ 1–2",
+            smartify("\"cats\" aren't dogs --- or birds... 'mostly' 1--2")
+        );
+        assert_eq!(
+            "<a href=\"/a.html\">“a”</a>",
+            smartify("<a href=\"/a.html\">\"a\"</a>")
+        );
+    }
+
+    #[test]
+    fn strip_and_normalize() {
+        assert_eq!(
+            "cats and dogs",
+            render("{{ '  <b>cats</b>\n\n and   dogs ' | strip_html | normalize_whitespace }}")
+        );
+    }
+}
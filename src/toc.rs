@@ -0,0 +1,218 @@
+use pulldown_cmark::{escape::escape_html, html, Event, Tag};
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::protect::restore_liquid;
+
+/// a heading in `page.toc.items`, any smaller headings that follow it are it's children
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TocEntry {
+    pub level: u32,
+    pub id: String,
+    pub title: String,
+    pub children: Vec<TocEntry>,
+}
+
+/// lower case letters, numbers and `-` so that the ids are stable and easy to link to
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-' || c == '_') && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_matches('-').to_string()
+}
+
+/// gives every heading an unique id (repeats get `-1`, `-2` etc. appended) and optionally a
+/// permalink anchor, headings up to `depth` are added to the table of contents.
+/// `protected` is the liquid that has been swapped out of the markdown so the titles can be
+/// restored, it isn't part of the ids
+pub fn headings<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+    anchors: bool,
+    depth: u32,
+    protected: &[String],
+    toc: &mut Vec<TocEntry>,
+) -> Vec<Event<'a>> {
+    let mut output = Vec::new();
+    let mut used = HashSet::new();
+    let mut heading: Option<Vec<Event<'a>>> = None;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading(_)) => heading = Some(Vec::new()),
+            Event::End(Tag::Heading(level)) => {
+                let inner = heading.take().unwrap_or_default();
+                let title: String = inner
+                    .iter()
+                    .filter_map(|e| match e {
+                        Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+                        _ => None,
+                    })
+                    .collect();
+                // the liquid hasn't been rendered yet so it's left out of the id, the title
+                // keeps it to be rendered later
+                let id = unique_id(&mut used, slugify(&title));
+                let title = restore_liquid(&title, protected);
+
+                let mut html = String::new();
+                html::push_html(&mut html, inner.into_iter());
+                if anchors {
+                    html += &format!(
+                        "<a class=\"anchor\" href=\"#{}\" aria-hidden=\"true\">#</a>",
+                        id
+                    );
+                }
+                output.push(Event::Html(
+                    format!("<h{0} id=\"{1}\">{2}</h{0}>\n", level, id, html).into(),
+                ));
+
+                if level <= depth {
+                    insert(
+                        toc,
+                        TocEntry {
+                            level,
+                            id,
+                            title,
+                            children: Vec::new(),
+                        },
+                    );
+                }
+            }
+            event => match heading.as_mut() {
+                Some(inner) => inner.push(event),
+                None => output.push(event),
+            },
+        }
+    }
+
+    output
+}
+
+/// nested `<ul>` lists of links to each heading
+pub fn toc_html(toc: &[TocEntry]) -> String {
+    if toc.is_empty() {
+        String::new()
+    } else {
+        format!("<ul class=\"toc\">{}</ul>", list_items(toc))
+    }
+}
+
+fn list_items(toc: &[TocEntry]) -> String {
+    let mut html = String::new();
+    for entry in toc {
+        html += &format!("<li><a href=\"#{}\">", entry.id);
+        // writing to a string can't fail
+        escape_html(&mut html, &entry.title).unwrap();
+        html += "</a>";
+        if !entry.children.is_empty() {
+            html += &format!("<ul>{}</ul>", list_items(&entry.children));
+        }
+        html += "</li>";
+    }
+    html
+}
+
+fn unique_id(used: &mut HashSet<String>, slug: String) -> String {
+    let slug = if slug.is_empty() {
+        String::from("section")
+    } else {
+        slug
+    };
+
+    let mut id = slug.clone();
+    let mut n = 0;
+    while !used.insert(id.clone()) {
+        n += 1;
+        id = format!("{}-{}", slug, n);
+    }
+    id
+}
+
+fn insert(toc: &mut Vec<TocEntry>, entry: TocEntry) {
+    match toc.last_mut() {
+        Some(last) if last.level < entry.level => insert(&mut last.children, entry),
+        _ => toc.push(entry),
+    }
+}
+
+#[cfg(test)]
+mod toc_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::Parser;
+
+    fn render(md: &str, anchors: bool, depth: u32) -> (String, Vec<TocEntry>) {
+        let mut toc = Vec::new();
+        let mut output = String::new();
+        html::push_html(
+            &mut output,
+            headings(Parser::new(md), anchors, depth, &[], &mut toc).into_iter(),
+        );
+        (output, toc)
+    }
+
+    #[test]
+    fn slugs() {
+        assert_eq!("hello-world", slugify("Hello, World!"));
+        assert_eq!("a-b-c", slugify(" a - b_c "));
+        assert_eq!("café", slugify("Café"));
+    }
+
+    #[test]
+    fn heading_ids() {
+        assert_eq!(
+            "<h1 id=\"cats\">cats</h1>\n<h2 id=\"dogs-code\">dogs <code>code</code></h2>\n",
+            render("# cats\n## dogs `code`", false, 3).0
+        );
+    }
+
+    #[test]
+    fn duplicate_heading_ids() {
+        assert_eq!(
+            "<h2 id=\"cats\">cats</h2>\n<h2 id=\"cats-1\">cats</h2>\n<h2 id=\"cats-2\">cats</h2>\n",
+            render("## cats\n## cats\n## cats", false, 3).0
+        );
+    }
+
+    #[test]
+    fn anchors() {
+        assert_eq!(
+            "<h2 id=\"cats\">cats<a class=\"anchor\" href=\"#cats\" aria-hidden=\"true\">#</a></h2>\n",
+            render("## cats", true, 3).0
+        );
+    }
+
+    #[test]
+    fn liquid_in_headings() {
+        let (template, protected) =
+            crate::protect::protect_liquid("## {{ page.title }}\n## {{ page.title }} notes");
+        let mut toc = Vec::new();
+        let mut output = String::new();
+        html::push_html(
+            &mut output,
+            headings(Parser::new(&template), false, 3, &protected, &mut toc).into_iter(),
+        );
+        assert_eq!(
+            vec![
+                ("section", "{{ page.title }}"),
+                ("notes", "{{ page.title }} notes")
+            ],
+            toc.iter()
+                .map(|entry| (entry.id.as_str(), entry.title.as_str()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn nested_toc() {
+        let (_, toc) = render("# a\n## b\n### c\n#### d\n## e\n# f", false, 3);
+        assert_eq!(
+            "<ul class=\"toc\"><li><a href=\"#a\">a</a><ul><li><a href=\"#b\">b</a><ul><li><a href=\"#c\">c</a></li></ul></li><li><a href=\"#e\">e</a></li></ul></li><li><a href=\"#f\">f</a></li></ul>",
+            toc_html(&toc)
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use liquid::ValueView;
+use liquid_core::{Display_filter, Filter, FilterReflection, ParseFilter, Value};
+
+use crate::config::SiteConfig;
+
+/// `baseurl` in front of the path like jekyll's `relative_url`, urls that already have a
+/// scheme e.g. `https://` are left as they are
+pub fn relative_url(baseurl: &str, input: &str) -> String {
+    if has_scheme(input) {
+        return input.to_string();
+    }
+    format!(
+        "{}{}",
+        leading_slash(baseurl.trim_end_matches('/')),
+        leading_slash(input)
+    )
+}
+
+/// `url` and `baseurl` in front of the path like jekyll's `absolute_url`
+pub fn absolute_url(url: &str, baseurl: &str, input: &str) -> String {
+    if has_scheme(input) {
+        return input.to_string();
+    }
+    format!(
+        "{}{}",
+        url.trim_end_matches('/'),
+        relative_url(baseurl, input)
+    )
+}
+
+/// `https:`, `mailto:` etc. at the start, or `//` for the same scheme as the page. A `:` later
+/// on e.g. `/r?to=http://x` is part of the path or query
+fn has_scheme(input: &str) -> bool {
+    if input.starts_with("//") {
+        return true;
+    }
+    match input.split_once(':') {
+        Some((scheme, _)) => {
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+fn leading_slash(path: &str) -> String {
+    if path.is_empty() || path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    }
+}
+
+#[derive(Clone, FilterReflection)]
+#[filter(
+    name = "relative_url",
+    description = "Prepends the site's baseurl to the path",
+    parsed(RelativeUrlFilter)
+)]
+pub struct RelativeUrl {
+    baseurl: String,
+}
+
+impl RelativeUrl {
+    pub fn new(config: &SiteConfig) -> Self {
+        RelativeUrl {
+            baseurl: config.baseurl.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Display_filter)]
+#[name = "relative_url"]
+struct RelativeUrlFilter {
+    baseurl: String,
+}
+
+impl ParseFilter for RelativeUrl {
+    fn parse(
+        &self,
+        _arguments: liquid_core::parser::FilterArguments,
+    ) -> liquid_core::Result<Box<dyn Filter>> {
+        Ok(Box::new(RelativeUrlFilter {
+            baseurl: self.baseurl.clone(),
+        }))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self as &dyn FilterReflection
+    }
+}
+
+impl Filter for RelativeUrlFilter {
+    fn evaluate(
+        &self,
+        input: &dyn ValueView,
+        _runtime: &liquid_core::Runtime,
+    ) -> liquid_core::Result<Value> {
+        if input.is_nil() {
+            return Ok(Value::Nil);
+        }
+        Ok(Value::scalar(relative_url(&self.baseurl, &input.to_kstr())))
+    }
+}
+
+#[derive(Clone, FilterReflection)]
+#[filter(
+    name = "absolute_url",
+    description = "Prepends the site's url and baseurl to the path",
+    parsed(AbsoluteUrlFilter)
+)]
+pub struct AbsoluteUrl {
+    url: String,
+    baseurl: String,
+}
+
+impl AbsoluteUrl {
+    pub fn new(config: &SiteConfig) -> Self {
+        AbsoluteUrl {
+            url: config.url.clone(),
+            baseurl: config.baseurl.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Display_filter)]
+#[name = "absolute_url"]
+struct AbsoluteUrlFilter {
+    url: String,
+    baseurl: String,
+}
+
+impl ParseFilter for AbsoluteUrl {
+    fn parse(
+        &self,
+        _arguments: liquid_core::parser::FilterArguments,
+    ) -> liquid_core::Result<Box<dyn Filter>> {
+        Ok(Box::new(AbsoluteUrlFilter {
+            url: self.url.clone(),
+            baseurl: self.baseurl.clone(),
+        }))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self as &dyn FilterReflection
+    }
+}
+
+impl Filter for AbsoluteUrlFilter {
+    fn evaluate(
+        &self,
+        input: &dyn ValueView,
+        _runtime: &liquid_core::Runtime,
+    ) -> liquid_core::Result<Value> {
+        if input.is_nil() {
+            return Ok(Value::Nil);
+        }
+        Ok(Value::scalar(absolute_url(
+            &self.url,
+            &self.baseurl,
+            &input.to_kstr(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod url_filter_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn relative() {
+        assert_eq!("/docs/a.html", relative_url("/docs", "a.html"));
+        assert_eq!("/docs/a.html", relative_url("/docs/", "/a.html"));
+        assert_eq!("/docs/a.html", relative_url("docs", "a.html"));
+        assert_eq!("/a.html", relative_url("", "a.html"));
+        assert_eq!("/docs", relative_url("/docs", ""));
+        assert_eq!(
+            "https://example.com/a.html",
+            relative_url("/docs", "https://example.com/a.html")
+        );
+        assert_eq!("mailto:a@b.c", relative_url("/docs", "mailto:a@b.c"));
+        assert_eq!(
+            "//cdn.example.com/a.js",
+            relative_url("/docs", "//cdn.example.com/a.js")
+        );
+        assert_eq!(
+            "/docs/r?to=http://x",
+            relative_url("/docs", "/r?to=http://x")
+        );
+        assert_eq!("/docs/a:b.html", relative_url("/docs", "/a:b.html"));
+    }
+
+    #[test]
+    fn absolute() {
+        assert_eq!(
+            "https://example.com/docs/a.html",
+            absolute_url("https://example.com/", "/docs", "a.html")
+        );
+        assert_eq!("/docs/a.html", absolute_url("", "/docs", "a.html"));
+    }
+
+    #[test]
+    fn filters() {
+        let config = SiteConfig {
+            url: String::from("https://example.com"),
+            baseurl: String::from("/docs"),
+            ..SiteConfig::default()
+        };
+        let parser = liquid::ParserBuilder::with_stdlib()
+            .filter(RelativeUrl::new(&config))
+            .filter(AbsoluteUrl::new(&config))
+            .build()
+            .unwrap();
+        assert_eq!(
+            "/docs/main.css https://example.com/docs/cats.html",
+            parser
+                .parse("{{ 'main.css' | relative_url }} {{ page.url | absolute_url }}")
+                .unwrap()
+                .render(&liquid::object!({ "page": { "url": "cats.html" } }))
+                .unwrap()
+        );
+    }
+}
@@ -4,18 +4,20 @@ use std::{ffi::OsString, fs::read_to_string};
 use crate::error::CustomError;
 
 /// there is probably a nice library for this but ahow
-pub fn search_dir(path: &PathBuf, underscore: bool) -> Vec<(PathBuf, OsString)> {
+pub fn search_dir(path: &Path, underscore: bool) -> Vec<(PathBuf, OsString)> {
     let mut f: Vec<(PathBuf, OsString)> = Vec::new();
-    for entry in path.read_dir().expect("read_dir call failed") {
-        if let Ok(entry) = entry {
-            if let Some(ending) = entry.path().extension() {
-                if !underscore
-                    || !path_file_name_to_string(&entry.path())
-                        .expect("Could not decode file name")
-                        .starts_with('_')
-                {
-                    f.push((entry.path(), ending.to_owned()));
-                }
+    for entry in path
+        .read_dir()
+        .expect("read_dir call failed")
+        .flatten()
+    {
+        if let Some(ending) = entry.path().extension() {
+            if !underscore
+                || !path_file_name_to_string(&entry.path())
+                    .expect("Could not decode file name")
+                    .starts_with('_')
+            {
+                f.push((entry.path(), ending.to_owned()));
             }
         }
     }
@@ -24,10 +26,11 @@ pub fn search_dir(path: &PathBuf, underscore: bool) -> Vec<(PathBuf, OsString)>
 }
 
 pub fn read_file(path: &Path) -> Result<String, CustomError> {
-    match read_to_string(path)?.parse::<String>() {
-        Ok(c) => Ok(c),
-        Err(e) => Err(CustomError::IOError(e.to_string())),
-    }
+    read_to_string(path).map_err(|e| {
+        CustomError::io("unable to read file")
+            .path(path)
+            .cause(e)
+    })
 }
 
 /// note: should only be used for .html files
@@ -35,15 +38,125 @@ pub fn path_file_name_to_string(file_path: &Path) -> Result<String, CustomError>
     Ok(file_path
         .file_name()
         .ok_or_else(|| {
-            CustomError::IOError(format!("Could not find file name from {:?}", file_path))
+            CustomError::io("Could not find file name").path(file_path)
         })?
         .to_str()
         .ok_or_else(|| {
-            CustomError::IOError(format!(
-                "Could not convert the file name into a valid utf-8 string {:?}",
-                file_path.file_name().unwrap()
-            ))
+            CustomError::io("Could not convert the file name into a valid utf-8 string")
+                .path(file_path)
         })?
         .to_owned()
         .replace(".html", ""))
 }
+
+/// the deepest directory that all of the paths are in
+pub fn common_ancestor(paths: &[&PathBuf]) -> PathBuf {
+    let mut paths = paths.iter();
+    let mut common = match paths.next() {
+        Some(path) => path.to_path_buf(),
+        None => return PathBuf::new(),
+    };
+    for path in paths {
+        while !path.starts_with(&common) && common.pop() {}
+    }
+    common
+}
+
+/// the `>` that closes the html tag starting at `start`, ones inside quoted attributes don't count
+pub fn tag_end(html: &str, start: usize) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in html[start..].char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return Some(start + i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// levenshtein distance, used to suggest what was meant when something isn't found
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            current.push(
+                (previous[j] + cost)
+                    .min(previous[j + 1] + 1)
+                    .min(current[j] + 1),
+            );
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// the candidates that are close enough to `name` to be a typo, nearest first
+pub fn suggestions<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let max = name.chars().count().div_ceil(3);
+    let mut close: Vec<(usize, &str)> = candidates
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|(d, c)| *d <= max && *c != name)
+        .collect();
+    close.sort();
+    close.into_iter().take(3).map(|(_, c)| c).collect()
+}
+
+/// a filter's argument that isn't one of the values it takes, in the same form as liquid's own
+pub fn invalid_argument(argument: &str, cause: &str) -> liquid_core::Error {
+    liquid_core::Error::with_msg("invalid argument")
+        .context("argument", argument.to_string())
+        .context("cause", cause.to_string())
+}
+
+/// renders `template` with liquid's standard library and the filters and tags that `add`
+/// registers, for the tests of each of them
+#[cfg(test)]
+pub fn render_with(
+    add: impl FnOnce(liquid::ParserBuilder) -> liquid::ParserBuilder,
+    template: &str,
+    globals: &liquid::Object,
+) -> liquid_core::Result<String> {
+    add(liquid::ParserBuilder::with_stdlib())
+        .build()
+        .unwrap()
+        .parse(template)?
+        .render(globals)
+}
+
+#[cfg(test)]
+mod util_tests {
+    use super::*;
+
+    #[test]
+    fn distance() {
+        assert_eq!(0, edit_distance("title", "title"));
+        assert_eq!(2, edit_distance("tilte", "title"));
+        assert_eq!(3, edit_distance("kitten", "sitting"));
+        assert_eq!(4, edit_distance("", "page"));
+    }
+
+    #[test]
+    fn ancestor() {
+        let articles = PathBuf::from("site/_articles/");
+        let site = PathBuf::from("site");
+        assert_eq!(site, common_ancestor(&[&articles, &site]));
+        assert_eq!(articles, common_ancestor(&[&articles]));
+        assert_eq!(
+            PathBuf::from("a"),
+            common_ancestor(&[&PathBuf::from("a/b"), &PathBuf::from("a/c")])
+        );
+    }
+
+    #[test]
+    fn suggest() {
+        let candidates = ["title", "tags", "date", "url", "description"];
+        assert_eq!(vec!["title"], suggestions("tilte", candidates.iter().copied()));
+        assert_eq!(vec!["url"], suggestions("ur", candidates.iter().copied()));
+        assert!(suggestions("nothing", candidates.iter().copied()).is_empty());
+    }
+}
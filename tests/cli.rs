@@ -1,3 +1,5 @@
+#![allow(clippy::needless_borrows_for_generic_args)]
+
 use assert_cmd::prelude::*;
 use predicates::prelude::*;
 use std::process;
@@ -22,3 +24,346 @@ pub fn build() {
         .assert()
         .success();
 }
+
+#[test]
+pub fn build_failure() {
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args(["build", "tests/resources/example3"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("build failed with 2 error(s)").from_utf8());
+}
+
+#[test]
+pub fn build_strict() {
+    // `{% include page.config.layout %}` is fine
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args(["build", "--strict", "tests/resources/example2"])
+        .assert()
+        .success();
+
+    // `{% include nav.html %}` still works but it's a warning
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args(["build", "tests/resources/example16"])
+        .assert()
+        .success();
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args(["build", "--strict", "tests/resources/example16"])
+        .assert()
+        .failure()
+        .stdout(
+            predicate::str::contains("[jekyll_include]")
+                .and(predicate::str::contains("default.html:2:13"))
+                .from_utf8(),
+        );
+}
+
+#[test]
+pub fn build_layout_error_reported_once() {
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args(["build", "tests/resources/example4"])
+        .assert()
+        .failure()
+        .stdout(
+            predicate::str::contains("files that use this template")
+                .count(1)
+                .and(predicate::str::contains("cats.md (layout)"))
+                .and(predicate::str::contains("dogs.md (layout)"))
+                .and(predicate::str::contains("help: did you mean `title`?"))
+                .and(predicate::str::contains("build failed with 1 error(s)"))
+                .from_utf8(),
+        );
+}
+
+#[test]
+pub fn build_unknown_layout() {
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args(["build", "tests/resources/example5"])
+        .assert()
+        .failure()
+        .stdout(
+            predicate::str::contains("layout 'pgae' not found")
+                .and(predicate::str::contains("help: did you mean `page`?"))
+                .and(predicate::str::contains("build failed with 1 error(s)"))
+                .from_utf8(),
+        );
+}
+
+#[test]
+pub fn build_unknown_link() {
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args(["build", "tests/resources/example6"])
+        .assert()
+        .failure()
+        .stdout(
+            predicate::str::contains("link not found")
+                .and(predicate::str::contains("_articles/broken.md:5:16"))
+                .and(predicate::str::contains(
+                    "help: did you mean one of `_articles/cats.md`",
+                ))
+                .and(predicate::str::contains("build failed with 1 error(s)"))
+                .from_utf8(),
+        );
+}
+
+#[test]
+pub fn build_check_links() {
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args([
+            "build",
+            "--check-links",
+            "--strict",
+            "tests/resources/example7",
+        ])
+        .assert()
+        .failure()
+        .stdout(
+            predicate::str::contains("'birds.html' no such file in the output")
+                .and(predicate::str::contains(
+                    "'cats.html#sleeping' no '#sleeping' in cats.html",
+                ))
+                .and(predicate::str::contains("[broken_link]"))
+                .and(predicate::str::contains(
+                    "external link (not checked) https://example.com",
+                ))
+                .and(predicate::str::contains(
+                    "build failed with 0 error(s) and 2 warning(s)",
+                ))
+                .from_utf8(),
+        );
+
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args(["check-links", "tests/resources/example7"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("build finished with 2 warning(s)").from_utf8());
+}
+
+#[test]
+pub fn build_minify() {
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args(["build", "--minify", "tests/resources/example8"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("build finished, minified html from").from_utf8());
+
+    let html = std::fs::read_to_string("tests/resources/example8/_output/cats.html").unwrap();
+    assert!(
+        html.starts_with(
+            "<!DOCTYPE html> <html> <head> <title>cats</title> <style>body{margin:0}</style>"
+        ),
+        "{}",
+        html
+    );
+    assert!(html.contains("<p>Cats are great.</p>"), "{}", html);
+    assert!(
+        html.contains("fn main() {\n    println!(&quot;meow&quot;);\n}"),
+        "{}",
+        html
+    );
+    assert!(!html.contains("<!--"), "{}", html);
+}
+
+#[test]
+pub fn build_fingerprint() {
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args([
+            "build",
+            "--check-links",
+            "--strict",
+            "tests/resources/example9",
+        ])
+        .assert()
+        .success();
+
+    let manifest: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string("tests/resources/example9/_output/manifest.json").unwrap(),
+    )
+    .unwrap();
+    let css = manifest["main.css"].as_str().unwrap();
+    assert!(
+        css.starts_with("main.") && css.len() == "main..css".len() + 8,
+        "{}",
+        css
+    );
+    let js = manifest["menu.js"].as_str().unwrap();
+    assert!(
+        js.starts_with("menu.") && js.len() == "menu..js".len() + 8,
+        "{}",
+        js
+    );
+    // crawlers ask for robots.txt by name so it keeps it
+    assert_eq!("robots.txt", manifest["robots.txt"].as_str().unwrap());
+    assert!(std::path::Path::new("tests/resources/example9/_output/robots.txt").exists());
+
+    let html = std::fs::read_to_string("tests/resources/example9/_output/cats.html").unwrap();
+    assert!(html.contains(&format!("href=\"/{}\"", css)), "{}", html);
+    assert!(html.contains(&format!("src=\"/{}\"", js)), "{}", html);
+    assert!(html.contains("href=\"/robots.txt\""), "{}", html);
+
+    // the original names are still there for links that don't use asset_url
+    assert!(html.contains("src=\"/cats.png\""), "{}", html);
+    let output = std::path::Path::new("tests/resources/example9/_output");
+    assert_eq!(
+        std::fs::read(output.join(css)).unwrap(),
+        std::fs::read(output.join("main.css")).unwrap()
+    );
+    assert!(output.join("cats.png").exists());
+    assert!(output.join(manifest["cats.png"].as_str().unwrap()).exists());
+}
+
+#[test]
+pub fn build_images() {
+    // a copy of an image that isn't there any more
+    let stale = std::path::Path::new("tests/resources/example10/.mole-cache/0123456789abcdef-16.png");
+    std::fs::create_dir_all(stale.parent().unwrap()).unwrap();
+    std::fs::write(stale, b"old").unwrap();
+
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args(["build", "--strict", "tests/resources/example10"])
+        .assert()
+        .success();
+
+    let html = std::fs::read_to_string("tests/resources/example10/_output/cats.html").unwrap();
+    assert!(
+        html.contains(
+            "<img src=\"/cats.png\" srcset=\"/cats-16.png 16w, /cats-32.png 32w, /cats.png 40w\" \
+             sizes=\"(max-width: 600px) 100vw, 600px\" width=\"40\" height=\"30\" alt=\"cats\" \
+             loading=\"lazy\">"
+        ),
+        "{}",
+        html
+    );
+    assert!(std::path::Path::new("tests/resources/example10/_output/cats-16.png").exists());
+    assert!(std::path::Path::new("tests/resources/example10/.mole-cache").is_dir());
+    assert!(!stale.exists());
+}
+
+#[test]
+pub fn build_images_webp() {
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args(["build", "tests/resources/example13"])
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("[image]: Could not resize image")
+                .and(predicate::str::contains("broken.png"))
+                .from_utf8(),
+        );
+
+    let html = std::fs::read_to_string("tests/resources/example13/_output/cats.html").unwrap();
+    assert!(
+        html.contains(
+            "<picture><source type=\"image/webp\" srcset=\"/cats-16.webp 16w, /cats-32.webp 32w, \
+             /cats-40.webp 40w\" sizes=\"100vw\"><img src=\"/cats.png\" srcset=\"/cats-16.png 16w, \
+             /cats-32.png 32w, /cats.png 40w\" sizes=\"100vw\""
+        ),
+        "{}",
+        html
+    );
+    // an image that can't be resized is still used as it is
+    assert!(html.contains("<img src=\"/broken.png\""), "{}", html);
+
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args(["build", "--strict", "tests/resources/example13"])
+        .assert()
+        .failure();
+}
+
+#[test]
+pub fn build_excerpt_warning() {
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args(["build", "--strict", "tests/resources/example11"])
+        .assert()
+        .failure()
+        .stdout(
+            predicate::str::contains("[excerpt]")
+                .and(predicate::str::contains("_articles/cats.md"))
+                .and(predicate::str::contains(
+                    "build failed with 0 error(s) and 1 warning(s)",
+                ))
+                .from_utf8(),
+        );
+}
+
+#[test]
+pub fn build_layout_loop_reported_once() {
+    let output = process::Command::cargo_bin("mole")
+        .unwrap()
+        .args(["build", "tests/resources/example12"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // once when it's found and once in the summary
+    assert_eq!(
+        2,
+        stdout
+            .matches("layout loop: default -> post -> default")
+            .count(),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("build failed with 1 error(s)"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+pub fn build_sass_error() {
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args(["build", "tests/resources/example14"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("main.scss:2:12").from_utf8())
+        .stdout(predicate::str::contains("build failed with 1 error(s)").from_utf8());
+}
+
+#[test]
+pub fn build_without_layouts() {
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args(["build", "--strict", "tests/resources/example17"])
+        .assert()
+        .success();
+
+    assert_eq!(
+        "<p>Cats are great.</p>",
+        std::fs::read_to_string("tests/resources/example17/_output/cats.html").unwrap()
+    );
+}
+
+#[test]
+pub fn build_expression_filters() {
+    process::Command::cargo_bin("mole")
+        .unwrap()
+        .args(["build", "--strict", "tests/resources/example15"])
+        .assert()
+        .success();
+
+    // `date` is in the site's timezone and `slugify` is there inside the expressions too
+    let html = std::fs::read_to_string("tests/resources/example15/_output/dogs.html").unwrap();
+    assert!(
+        html.contains("[4: Big Cats][2: dogs] big-cats,dogs"),
+        "{}",
+        html
+    );
+}
@@ -0,0 +1 @@
+document.body.classList.add("js");